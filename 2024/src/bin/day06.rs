@@ -4,21 +4,159 @@
 //! the guard when hitting an obstacle instead of incrementing its position 1 by 1.
 //!
 //! Part 2: the big win is only trying to put obstacles in the positions walked by the guard
-//! during part 1.
+//! during part 1. Each check resumes from the state right before the guard first reaches
+//! the candidate, and rayon's `map_init` hands every worker its own `seen` buffer.
 //!
 //! Gotcha for part 2: when using a Vec as boolean array, you gotta `.fill(false)` instead of
 //! `.clear()` it.
 //!
 //! Optimized to use teleport maps.
+//!
+//! Debugging: `--trace grid|json` walks the guard tile by tile with [`GuardSim`]
+//! and dumps the path plus the loop-causing obstacles to stderr. Extra obstacles
+//! can be dropped in with `--obstacle x,y;x,y...`, the loop-causing ones are then
+//! those of the new route.
 
-use std::thread::available_parallelism;
-
+use aoc_2024::{extract_nums, flag};
 use aoc_2dmap::prelude::*;
 use aoc_prelude::{HashSet, Itertools};
 use rayon::prelude::*;
 
 type Teleport = Vec<Pos>;
 
+const DIR_CHARS: [char; 4] = ['^', '>', 'v', '<'];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Event {
+    Step,
+    Turn,
+    Exit,
+    Loop,
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Step => "step",
+            Event::Turn => "turn",
+            Event::Exit => "exit",
+            Event::Loop => "loop",
+        }
+    }
+}
+
+/// Walks the guard one tile at a time, yielding every `(pos, dir)` state along
+/// with what happens there. Stops after an [`Event::Exit`] or [`Event::Loop`].
+struct GuardSim<'a> {
+    map: &'a Map<u8>,
+    obstacles: HashSet<Pos>,
+    pos: Pos,
+    dir: usize,
+    seen: Vec<bool>,
+    done: bool,
+}
+
+impl<'a> GuardSim<'a> {
+    fn new(map: &'a Map<u8>, start: Pos) -> Self {
+        Self {
+            map,
+            obstacles: HashSet::new(),
+            pos: start,
+            dir: 0,
+            seen: vec![false; (map.size.x * map.size.y) as usize * 4],
+            done: false,
+        }
+    }
+
+    fn with_obstacle(mut self, obs: Pos) -> Self {
+        self.obstacles.insert(obs);
+        self
+    }
+
+    fn is_blocked(&self, pos: Pos) -> bool {
+        self.map.get(pos) == Some(b'#') || self.obstacles.contains(&pos)
+    }
+}
+
+impl Iterator for GuardSim<'_> {
+    type Item = (Pos, usize, Event);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let (pos, dir) = (self.pos, self.dir);
+
+        let k_ = key(pos, dir, self.map.size);
+        let event = if self.seen[k_] {
+            Event::Loop
+        } else {
+            self.seen[k_] = true;
+            let ahead = pos + ORTHOGONAL[dir];
+            if !self.map.within(ahead) {
+                Event::Exit
+            } else if self.is_blocked(ahead) {
+                self.dir = turn_right(dir);
+                Event::Turn
+            } else {
+                self.pos = ahead;
+                Event::Step
+            }
+        };
+
+        self.done = matches!(event, Event::Exit | Event::Loop);
+        Some((pos, dir, event))
+    }
+}
+
+/// The states walked by a [`GuardSim`] and the obstacles that would trap the guard.
+struct Trace {
+    path: Vec<(Pos, usize, Event)>,
+    obstacles: Vec<Pos>,
+    loop_obstacles: Vec<Pos>,
+}
+
+impl Trace {
+    fn render(&self, map: &Map<u8>) -> String {
+        let mut grid = Map::new(map.size, map.iter().map(|p| map[p] as char));
+        for &(pos, _, _) in &self.path {
+            grid[pos] = 'X';
+        }
+        for &obs in &self.loop_obstacles {
+            grid[obs] = 'O';
+        }
+        for &obs in &self.obstacles {
+            grid[obs] = '#';
+        }
+        if let Some(&(pos, dir, _)) = self.path.first() {
+            grid[pos] = DIR_CHARS[dir];
+        }
+        (0..grid.size.y).map(|r| grid.get_row(r).join("")).join("\n")
+    }
+
+    fn to_json(&self) -> String {
+        let path = self
+            .path
+            .iter()
+            .map(|(p, dir, ev)| {
+                format!(
+                    r#"{{"x":{},"y":{},"dir":"{}","event":"{}"}}"#,
+                    p.x,
+                    p.y,
+                    DIR_CHARS[*dir],
+                    ev.name()
+                )
+            })
+            .join(",");
+        let positions = |ps: &[Pos]| ps.iter().map(|p| format!("[{},{}]", p.x, p.y)).join(",");
+        format!(
+            r#"{{"path":[{path}],"obstacles":[{}],"loop_obstacles":[{}]}}"#,
+            positions(&self.obstacles),
+            positions(&self.loop_obstacles)
+        )
+    }
+}
+
 fn solve() -> (usize, usize) {
     let input = include_str!("../../inputs/06.in");
     let map_size = Pos::from((
@@ -30,46 +168,74 @@ fn solve() -> (usize, usize) {
     let start = find_tile(&map, b'^');
     let teleport = make_teleport(&map);
 
-    let mut pos = start;
-    let mut dir = 0;
-
-    // let mut visited = vec![false; (map.size.x * map.size.y) as usize];
-    let mut visited = HashSet::new();
-    while map.within(pos + ORTHOGONAL[dir]) {
-        let new_pos = teleport[key(pos, dir, map.size)];
+    // Remember the state right before the guard first steps on each tile: an obstacle
+    // placed there can't have changed the path up to that point.
+    let mut reached = vec![false; (map.size.x * map.size.y) as usize];
+    reached[index(start, map.size)] = true;
 
-        for x in pos.x.min(new_pos.x)..=new_pos.x.max(pos.x) {
-            for y in pos.y.min(new_pos.y)..=new_pos.y.max(pos.y) {
-                let gallivant = Pos::new(x, y);
-                if map.within(gallivant) {
-                    visited.insert(gallivant);
-                }
-            }
+    let mut candidates = Vec::with_capacity(8192);
+    for (pos, dir, event) in GuardSim::new(&map, start) {
+        let next = pos + ORTHOGONAL[dir];
+        if event == Event::Step && !reached[index(next, map.size)] {
+            reached[index(next, map.size)] = true;
+            candidates.push((next, pos, dir));
         }
-
-        pos = new_pos;
-        dir = turn_right(dir);
     }
 
-    let p1 = visited.len();
+    let p1 = candidates.len() + 1;
+
+    let loop_obstacles: Vec<Pos> = candidates
+        .par_iter()
+        .map_init(
+            || vec![false; (map.size.x * map.size.y) as usize * 4],
+            |seen, &(obs, pos, dir)| {
+                has_cycle(&map, (pos, dir), obs, &teleport, seen).then_some(obs)
+            },
+        )
+        .flatten()
+        .collect();
 
-    let p2 = visited
-        .into_iter()
-        .collect_vec()
-        .chunks(p1 / available_parallelism().unwrap().get())
-        .par_bridge()
-        .map(|chunk| {
-            let mut seen = vec![false; (map.size.x * map.size.y) as usize * 4];
-            chunk
+    let p2 = loop_obstacles.len();
+
+    if let Some(format) = flag("trace") {
+        let obstacles =
+            flag("obstacle").map_or(Vec::new(), |s| s.split(';').map(parse_pos).collect_vec());
+        let sim = |extra: Option<Pos>| {
+            obstacles
                 .iter()
-                .map(|obs| has_cycle(&map, start, *obs, &teleport, &mut seen) as usize)
-                .sum::<usize>()
-        })
-        .sum();
+                .chain(&extra)
+                .fold(GuardSim::new(&map, start), |sim, &o| sim.with_obstacle(o))
+        };
+        let path = sim(None).collect_vec();
+
+        // the extra obstacles change the route, so its loop obstacles have to be found anew
+        let loop_obstacles = match obstacles.is_empty() {
+            true => loop_obstacles,
+            false => path
+                .iter()
+                .map(|&(pos, _, _)| pos)
+                .filter(|&pos| pos != start)
+                .unique()
+                .collect_vec()
+                .into_par_iter()
+                .filter(|&obs| sim(Some(obs)).last().map(|(_, _, ev)| ev) == Some(Event::Loop))
+                .collect(),
+        };
+        let trace = Trace { path, obstacles, loop_obstacles };
+        match format.as_str() {
+            "json" => eprintln!("{}", trace.to_json()),
+            _ => eprintln!("{}", trace.render(&map)),
+        }
+    }
 
     (p1, p2)
 }
 
+fn parse_pos(s: &str) -> Pos {
+    let (x, y) = extract_nums(s).collect_tuple().expect("expected x,y");
+    Pos::new(x, y)
+}
+
 fn make_teleport(map: &Map<u8>) -> Teleport {
     let mut teleport = vec![Pos::new(0, 0); (map.size.x * map.size.y) as usize * 4];
     // Initially, all points teleport outside the map
@@ -102,10 +268,16 @@ fn make_teleport(map: &Map<u8>) -> Teleport {
     teleport
 }
 
-fn has_cycle(map: &Map<u8>, start: Pos, obs: Pos, teleport: &Teleport, seen: &mut [bool]) -> bool {
+fn has_cycle(
+    map: &Map<u8>,
+    (start, start_dir): (Pos, usize),
+    obs: Pos,
+    teleport: &Teleport,
+    seen: &mut [bool],
+) -> bool {
     seen.fill(false);
     let mut cur = start;
-    let mut dir = 0;
+    let mut dir = start_dir;
 
     while map.within(cur) {
         let k_ = key(cur, dir, map.size);
@@ -138,7 +310,10 @@ fn has_cycle(map: &Map<u8>, start: Pos, obs: Pos, teleport: &Teleport, seen: &mu
 fn find_tile(map: &Map<u8>, tile: u8) -> Pos { map.iter().find(|pos| map[pos] == tile).unwrap() }
 
 #[inline]
-fn key(p: Pos, dir: usize, map_size: Pos) -> usize { ((p.x * map_size.y + p.y) * 4) as usize + dir }
+fn index(p: Pos, map_size: Pos) -> usize { (p.x * map_size.y + p.y) as usize }
+
+#[inline]
+fn key(p: Pos, dir: usize, map_size: Pos) -> usize { index(p, map_size) * 4 + dir }
 
 #[inline]
 fn turn_right(dir: usize) -> usize { (dir + 1) % 4 }
@@ -3,21 +3,89 @@
 //! Work in reverse order from the end of the equation to prune as many states
 //! as possible by checking which operations are possible and reversing the operation.
 //!
-//! Bonus points for using fancy match patterns in the `check()` function.
-use aoc_prelude::num_integer::Integer;
+//! Bonus points for using fancy match patterns in the `reverse_search()` function.
+//!
+//! Operators are pluggable through the [`Operator`] trait: each one only needs to
+//! know how to recover its left operand from the result and its right operand.
+//! `--ops "+ * || - / ||2"` checks the equations against a custom set and
+//! `--show` prints the operator sequence that solves each equation.
+use aoc_2024::flag;
+use aoc_prelude::{num_integer::Integer, Itertools};
+
+type Int = u128;
+
+trait Operator {
+    /// Left operand such that `left <op> right == result`, if there is one.
+    fn unapply(&self, result: Int, right: Int) -> Option<Int>;
+
+    fn symbol(&self) -> String;
+}
 
-type Int = u64;
-type Op = u8;
+struct Add;
+struct Mul;
+struct Sub;
+/// Exact integer division: a truncating one wouldn't have a unique reverse step.
+struct Div;
+/// Concatenates the digits of both operands written in `base`.
+struct Cat(Int);
 
-const ADD: Op = 0;
-const MUL: Op = 1;
-const CAT: Op = 2;
+impl Operator for Add {
+    fn unapply(&self, result: Int, right: Int) -> Option<Int> { result.checked_sub(right) }
+
+    fn symbol(&self) -> String { "+".into() }
+}
+
+impl Operator for Mul {
+    fn unapply(&self, result: Int, right: Int) -> Option<Int> {
+        if right == 0 {
+            return None;
+        }
+        let (left, rem) = result.div_rem(&right);
+        (rem == 0).then_some(left)
+    }
+
+    fn symbol(&self) -> String { "*".into() }
+}
+
+impl Operator for Sub {
+    fn unapply(&self, result: Int, right: Int) -> Option<Int> { result.checked_add(right) }
+
+    fn symbol(&self) -> String { "-".into() }
+}
+
+impl Operator for Div {
+    fn unapply(&self, result: Int, right: Int) -> Option<Int> {
+        (right != 0).then(|| result.checked_mul(right)).flatten()
+    }
+
+    fn symbol(&self) -> String { "/".into() }
+}
+
+impl Operator for Cat {
+    fn unapply(&self, result: Int, right: Int) -> Option<Int> {
+        let (left, rem) = result.checked_sub(right)?.div_rem(&grade(right, self.0)?);
+        (rem == 0).then_some(left)
+    }
+
+    fn symbol(&self) -> String {
+        match self.0 {
+            10 => "||".into(),
+            base => format!("||{base}"),
+        }
+    }
+}
 
 fn solve() -> (Int, Int) {
     let mut operands = Vec::with_capacity(20);
 
+    let p1_ops: [&dyn Operator; 2] = [&Add, &Mul];
+    let p2_ops: [&dyn Operator; 3] = [&Add, &Mul, &Cat(10)];
+    let custom_ops = flag("ops").map(|ops| ops.split_whitespace().map(parse_op).collect_vec());
+    let show = flag("show").is_some();
+
     let mut p1 = 0;
     let mut p2 = 0;
+    let mut custom = 0;
 
     include_str!("../../inputs/07.in").lines().for_each(|line| {
         let (first, rest) = line.split_once(":").unwrap();
@@ -25,50 +93,102 @@ fn solve() -> (Int, Int) {
         operands.clear();
         operands.extend(rest.split_ascii_whitespace().filter_map(|el| el.parse::<Int>().ok()));
 
-        if check::<MUL>(expected, &operands) {
+        let solution = if let Some(ops) = check(expected, &operands, &p1_ops) {
             p1 += expected;
-            p2 += expected
-        } else if check::<CAT>(expected, &operands) {
             p2 += expected;
+            Some(ops)
+        } else if let Some(ops) = check(expected, &operands, &p2_ops) {
+            p2 += expected;
+            Some(ops)
+        } else {
+            None
+        };
+
+        if let Some(ops) = solution.filter(|_| show) {
+            eprintln!("{}", render(expected, &operands, &ops));
+        }
+
+        if let Some(custom_ops) = &custom_ops {
+            let custom_ops = custom_ops.iter().map(|op| op.as_ref()).collect_vec();
+            if let Some(ops) = check(expected, &operands, &custom_ops) {
+                custom += expected;
+                if show {
+                    eprintln!("{} (custom)", render(expected, &operands, &ops));
+                }
+            }
         }
     });
 
+    if custom_ops.is_some() {
+        eprintln!("Custom operators: {custom}");
+    }
+
     (p1, p2)
 }
 
+/// Operators, from left to right, that turn `operands` into `expected`.
+fn check<'a>(
+    expected: Int,
+    operands: &[Int],
+    ops: &[&'a dyn Operator],
+) -> Option<Vec<&'a dyn Operator>> {
+    let mut sequence = Vec::with_capacity(operands.len());
+    reverse_search(expected, operands, ops, &mut sequence).then(|| {
+        sequence.reverse();
+        sequence
+    })
+}
+
 #[inline(never)]
-fn check<const O: Op>(expected: Int, operands: &[Int]) -> bool {
+fn reverse_search<'a>(
+    expected: Int,
+    operands: &[Int],
+    ops: &[&'a dyn Operator],
+    sequence: &mut Vec<&'a dyn Operator>,
+) -> bool {
     match operands {
         [] => false,
         [last] => expected == *last,
-        [rest @ .., last] => (0..=O).any(|op| {
-            can_proceed(op, expected, *last)
-                .is_some_and(|prev_expected| check::<O>(prev_expected, rest))
+        [rest @ .., last] => ops.iter().any(|&op| {
+            op.unapply(expected, *last).is_some_and(|prev_expected| {
+                sequence.push(op);
+                let found = reverse_search(prev_expected, rest, ops, sequence);
+                if !found {
+                    sequence.pop();
+                }
+                found
+            })
         }),
     }
 }
 
-fn can_proceed(op: Op, exp: Int, operand: Int) -> Option<Int> {
-    match op {
-        ADD => (exp >= operand).then(|| exp - operand),
-        MUL => (exp % operand == 0).then(|| exp / operand),
-        CAT => {
-            let (d, r) = (exp - operand).div_rem(&grade(operand));
-            (r == 0).then_some(d)
-        }
-        _ => unreachable!(),
+fn render(expected: Int, operands: &[Int], ops: &[&dyn Operator]) -> String {
+    let tail = ops.iter().zip(&operands[1..]).map(|(op, num)| format!(" {} {num}", op.symbol()));
+    format!("{expected}: {}{}", operands[0], tail.collect::<String>())
+}
+
+fn parse_op(symbol: &str) -> Box<dyn Operator> {
+    match symbol {
+        "+" => Box::new(Add),
+        "*" => Box::new(Mul),
+        "-" => Box::new(Sub),
+        "/" => Box::new(Div),
+        "||" => Box::new(Cat(10)),
+        _ => match symbol.strip_prefix("||").and_then(|base| base.parse().ok()) {
+            Some(base) if base >= 2 => Box::new(Cat(base)),
+            _ => panic!("unknown operator: {symbol}"),
+        },
     }
 }
 
+/// Smallest power of `base` that's larger than `what`.
 #[inline]
-fn grade(what: Int) -> Int {
-    if what < 10 {
-        10
-    } else if what < 100 {
-        100
-    } else {
-        1000
+fn grade(what: Int, base: Int) -> Option<Int> {
+    let mut grade = base;
+    while grade <= what {
+        grade = grade.checked_mul(base)?;
     }
+    Some(grade)
 }
 
 aoc_2024::main! {
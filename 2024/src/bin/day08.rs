@@ -2,8 +2,23 @@
 //!
 //! Brute force all the way, but fast enough for a Sunday problem, especially
 //! after switching to boolean arrays instead of our fancy `Map`.
+//!
+//! Harmonics step by the antenna delta reduced by its gcd, so pairs like
+//! (0,0)-(2,4) also hit the grid points in between them. Any character other
+//! than `.` is an antenna, Unicode included.
+//!
+//! `--render near|harmonics` overlays the antinodes on the grid and lists them
+//! per frequency.
+use aoc_2024::flag;
 use aoc_2dmap::prelude::*;
-use aoc_prelude::{BTreeMap, Itertools};
+use aoc_prelude::{num_integer::Integer, BTreeMap, Itertools};
+
+/// Antinodes produced by all antenna pairs of a single frequency.
+#[derive(Default)]
+struct Antinodes {
+    near: Vec<Pos>,
+    harmonics: Vec<Pos>,
+}
 
 fn solve() -> (usize, usize) {
     let input = include_str!("../../inputs/08.in");
@@ -13,45 +28,90 @@ fn solve() -> (usize, usize) {
     ));
     let map = Map::new(map_size, input.chars().filter(|&c| c != '\n'));
 
-    let mut h_map = BTreeMap::<char, Vec<Pos>>::new();
-    for pos in map.iter() {
-        let ch = map[pos];
-        if ch != '.' {
-            h_map.entry(ch).or_default().push(pos);
-        }
-    }
+    let resonance = resonate(&map);
 
     let mut p1_vec = vec![false; (map.size.x * map.size.y) as usize];
     let mut p2_vec = vec![false; (map.size.x * map.size.y) as usize];
 
-    for antennas in h_map.values() {
-        antennas.iter().tuple_combinations().for_each(|(p1, p2)| {
-            antinodes(p1, p2, &map.size, &mut p1_vec, &mut p2_vec);
-            antinodes(p2, p1, &map.size, &mut p1_vec, &mut p2_vec);
-        });
+    for antinodes in resonance.values() {
+        antinodes.near.iter().for_each(|p| p1_vec[index(p, &map.size)] = true);
+        antinodes.harmonics.iter().for_each(|p| p2_vec[index(p, &map.size)] = true);
     }
 
     let p1 = p1_vec.into_iter().filter(|&x| x).count();
     let p2 = p2_vec.into_iter().filter(|&x| x).count();
 
+    if let Some(which) = flag("render") {
+        let harmonics = which != "near";
+        eprintln!("{}", render(&map, &resonance, harmonics));
+        for (freq, antinodes) in &resonance {
+            let positions = if harmonics { &antinodes.harmonics } else { &antinodes.near };
+            eprintln!(
+                "{freq}: {}",
+                positions.iter().map(|p| format!("({},{})", p.x, p.y)).join(" ")
+            );
+        }
+    }
+
     (p1, p2)
 }
 
-fn antinodes(a1: &Pos, a2: &Pos, map_size: &MapSize, p1_buf: &mut [bool], p2_buf: &mut [bool]) {
+/// Antinodes for each frequency on the map.
+fn resonate(map: &Map<char>) -> BTreeMap<char, Antinodes> {
+    let mut h_map = BTreeMap::<char, Vec<Pos>>::new();
+    for pos in map.iter() {
+        let ch = map[pos];
+        if ch != '.' {
+            h_map.entry(ch).or_default().push(pos);
+        }
+    }
+
+    h_map
+        .into_iter()
+        .map(|(freq, antennas)| {
+            let mut res = Antinodes::default();
+            antennas.iter().tuple_combinations().for_each(|(a1, a2)| {
+                antinodes(a1, a2, &map.size, &mut res);
+            });
+            for positions in [&mut res.near, &mut res.harmonics] {
+                positions.sort_unstable_by_key(|p| (p.y, p.x));
+                positions.dedup();
+            }
+            (freq, res)
+        })
+        .collect()
+}
+
+fn antinodes(a1: &Pos, a2: &Pos, map_size: &MapSize, res: &mut Antinodes) {
     let dist = *a2 - *a1;
+    res.near.extend([*a2 + dist, *a1 - dist].into_iter().filter(|p| within(p, map_size)));
 
-    let mut cand = *a2;
-    let mut i = 0;
+    let g = dist.x.gcd(&dist.y);
+    let step = Pos::new(dist.x / g, dist.y / g);
 
+    // rewind to the edge of the map, then sweep the whole line
+    let back = Pos::new(-step.x, -step.y);
+    let mut cand = *a1;
+    while within(&(cand + back), map_size) {
+        cand += back;
+    }
     while within(&cand, map_size) {
-        let idx = index(&cand, map_size);
-        p2_buf[idx] = true;
-        if i == 1 {
-            p1_buf[idx] = true;
+        res.harmonics.push(cand);
+        cand += step;
+    }
+}
+
+fn render(map: &Map<char>, resonance: &BTreeMap<char, Antinodes>, harmonics: bool) -> String {
+    let mut overlay = Map::new(map.size, map.iter().map(|p| map[p]));
+    for antinodes in resonance.values() {
+        let positions = if harmonics { &antinodes.harmonics } else { &antinodes.near };
+        for p in positions {
+            if overlay[p] == '.' {
+                overlay[p] = '#';
+            }
         }
-        cand += dist;
-        i += 1;
     }
+    (0..overlay.size.y).map(|r| overlay.get_row(r).join("")).join("\n")
 }
 
 #[inline]
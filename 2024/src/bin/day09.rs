@@ -10,11 +10,49 @@
 //!
 //! Gotcha was to stop moving files if the destination index is higher than the file
 //! index.
+//!
+//! The disk map can also be given as comma-separated sizes (`12,3,10`) for extents
+//! wider than a single digit, in which case we keep one heap per free extent size.
+//! `--layout first-fit|whole-file|best-fit` compacts with the given [`Policy`] and
+//! prints the resulting block layout.
 use std::{
     cmp::{max, Reverse},
     collections::BinaryHeap,
 };
 
+use aoc_2024::flag;
+use aoc_prelude::Itertools;
+
+/// How files get moved into free space.
+#[derive(Debug, Copy, Clone)]
+enum Policy {
+    /// Fill the leftmost free block, splitting files as needed (part 1).
+    FirstFit,
+    /// Move whole files into the leftmost gap that fits them (part 2).
+    WholeFile,
+    /// Move whole files into the tightest gap that fits them.
+    BestFit,
+}
+
+impl From<&str> for Policy {
+    fn from(value: &str) -> Self {
+        match value {
+            "first-fit" => Policy::FirstFit,
+            "whole-file" => Policy::WholeFile,
+            "best-fit" => Policy::BestFit,
+            _ => panic!("unknown policy: {value}"),
+        }
+    }
+}
+
+/// A contiguous run of blocks belonging to a single file.
+#[derive(Debug, Copy, Clone)]
+struct Run {
+    file_no: usize,
+    start: usize,
+    len: usize,
+}
+
 #[derive(Debug, Copy, Clone)]
 struct Fext {
     file_no: usize,
@@ -43,28 +81,93 @@ impl Ext {
     }
 }
 
-fn solve() -> (usize, usize) {
-    let mut p1_exts = Vec::with_capacity(10000);
-    let mut start = 0;
-    let mut spaces = vec![BinaryHeap::new(); 10];
-
-    include_str!("../../inputs/09.in").trim().chars().enumerate().for_each(|(idx, c)| {
-        let size = ((c as u8) - b'0') as usize;
-        if is_file(idx) {
-            p1_exts.push(Ext { files: vec![Fext { file_no: idx / 2, size }], start, free: 0 });
-            start += size;
+#[derive(Clone)]
+struct Disk {
+    exts: Vec<Ext>,
+    spaces: Vec<BinaryHeap<Reverse<usize>>>,
+    len: usize,
+}
+
+impl Disk {
+    fn parse(input: &str) -> Self {
+        let sizes = if input.contains(',') {
+            input.split(',').map(|s| s.trim().parse::<usize>().unwrap()).collect_vec()
         } else {
-            spaces[size].push(Reverse(idx));
-            p1_exts.push(Ext { files: Vec::new(), start, free: size });
+            input.chars().map(|c| ((c as u8) - b'0') as usize).collect_vec()
+        };
+
+        let max_free = sizes.iter().skip(1).step_by(2).max().copied().unwrap_or(0);
+        let mut exts = Vec::with_capacity(sizes.len());
+        let mut spaces = vec![BinaryHeap::new(); max_free + 1];
+        let mut start = 0;
+
+        sizes.into_iter().enumerate().for_each(|(idx, size)| {
+            if is_file(idx) {
+                exts.push(Ext { files: vec![Fext { file_no: idx / 2, size }], start, free: 0 });
+            } else {
+                spaces[size].push(Reverse(idx));
+                exts.push(Ext { files: Vec::new(), start, free: size });
+            }
             start += size;
+        });
+
+        // trailing free space never gets any files, but the tail pointers expect a file
+        if !is_file(exts.len() - 1) {
+            exts.pop();
         }
-    });
-    let mut p2_exts = p1_exts.clone();
 
-    part1(&mut p1_exts);
-    part2(&mut p2_exts, &mut spaces);
+        Self { exts, spaces, len: start }
+    }
+
+    fn compact(mut self, policy: Policy) -> Self {
+        match policy {
+            Policy::FirstFit => part1(&mut self.exts),
+            Policy::WholeFile => part2(&mut self.exts, &mut self.spaces, false),
+            Policy::BestFit => part2(&mut self.exts, &mut self.spaces, true),
+        }
+        self
+    }
+
+    fn layout(&self) -> Vec<Run> {
+        let mut runs = Vec::with_capacity(self.exts.len());
+        for ext in &self.exts {
+            let mut start = ext.start;
+            for f in ext.files.iter().filter(|f| f.size > 0) {
+                runs.push(Run { file_no: f.file_no, start, len: f.size });
+                start += f.size;
+            }
+        }
+        runs.sort_unstable_by_key(|r| r.start);
+        runs
+    }
+
+    /// Puzzle notation, file numbers wrap around after 9.
+    fn render(&self) -> String {
+        let mut blocks = vec!['.'; self.len];
+        for run in self.layout() {
+            let digit = char::from_digit((run.file_no % 10) as u32, 10).unwrap();
+            blocks[run.start..run.start + run.len].fill(digit);
+        }
+        blocks.into_iter().collect()
+    }
+}
 
-    (checksum(&p1_exts), checksum(&p2_exts))
+fn solve() -> (usize, usize) {
+    let disk = Disk::parse(include_str!("../../inputs/09.in").trim());
+
+    if let Some(policy) = flag("layout") {
+        let disk = disk.clone().compact(Policy::from(policy.as_str()));
+        eprintln!("{}", disk.render());
+        for run in disk.layout() {
+            eprintln!("{} {} {}", run.file_no, run.start, run.len);
+        }
+        eprintln!("Checksum: {}", checksum(&disk.exts));
+    }
+
+    let p1_disk = disk.clone().compact(Policy::FirstFit);
+    let p2_disk = disk.compact(Policy::WholeFile);
+
+    (checksum(&p1_disk.exts), checksum(&p2_disk.exts))
 }
 
 fn part1(exts: &mut [Ext]) {
@@ -103,7 +206,7 @@ fn part1(exts: &mut [Ext]) {
     }
 }
 
-fn part2(exts: &mut [Ext], spaces: &mut [BinaryHeap<Reverse<usize>>]) {
+fn part2(exts: &mut [Ext], spaces: &mut [BinaryHeap<Reverse<usize>>], best_fit: bool) {
     let exts_len = exts.len();
 
     let mut max_move = usize::MAX;
@@ -117,11 +220,16 @@ fn part2(exts: &mut [Ext], spaces: &mut [BinaryHeap<Reverse<usize>>]) {
             return;
         }
 
-        if let Some(Reverse(idx)) = (tail_file.size..10)
-            .filter_map(|bucket| spaces[bucket].peek().take_if(|x| x.0 <= j).map(|x| (bucket, x.0)))
-            .min_by(|(_, idx1), (_, idx2)| idx1.cmp(idx2))
-            .and_then(|(bucket, _)| spaces[bucket].pop())
-        {
+        // buckets are visited smallest first, so best-fit takes the first one
+        let mut fits = (tail_file.size..spaces.len()).filter_map(|bucket| {
+            spaces[bucket].peek().take_if(|x| x.0 <= j).map(|x| (bucket, x.0))
+        });
+        let dest = match best_fit {
+            true => fits.next(),
+            false => fits.min_by(|(_, idx1), (_, idx2)| idx1.cmp(idx2)),
+        };
+
+        if let Some(Reverse(idx)) = dest.and_then(|(bucket, _)| spaces[bucket].pop()) {
             exts[idx].files.push(tail_file);
             exts[idx].free -= tail_file.size;
             if exts[idx].free > 0 {
@@ -1,31 +1,33 @@
 //! # Hoof It
 //!
-//! Both parts solvable through DFS: set of unique positions for the end goals (9) for part 1,
-//! and number of times we reach the end goal for part 2.
-use std::collections::VecDeque;
-
+//! Dynamic programming over the heights, from the summits down to the trailheads:
+//! every tile inherits the union of the reachable summits (as a bitset) and the sum
+//! of the path counts of its uphill neighbours. Part 1 is the number of bits set
+//! for each trailhead, part 2 the number of paths.
+//!
+//! Only two consecutive height levels are kept around, so the map can be of any size.
+//! Heights can go up to `z` (35), any other tile is impassable, and the trail shape
+//! is configurable through `--from 0 --to 9 --step 1`.
+use aoc_2024::param;
 use aoc_2dmap::prelude::{Map, Pos};
-use aoc_prelude::HashSet;
 
-struct Buf {
-    seen: Vec<bool>,
-    ends: HashSet<Pos>,
-    queue: VecDeque<Pos>,
+type Height = i32;
+
+/// Heights visited by a trail: `from`, `from + step`, ..., `to`.
+struct Ladder {
+    from: Height,
+    to: Height,
+    step: Height,
 }
 
-impl Buf {
-    fn sized<const N: usize>() -> Self {
-        Self {
-            seen: vec![false; N],
-            ends: HashSet::with_capacity(16),
-            queue: VecDeque::with_capacity(64),
-        }
-    }
+impl Ladder {
+    fn num_levels(&self) -> usize { ((self.to - self.from) / self.step) as usize + 1 }
 
-    fn clear(&mut self) {
-        self.seen.fill(false);
-        self.ends.clear();
-        self.queue.clear();
+    fn level(&self, height: Height) -> Option<usize> {
+        let delta = height - self.from;
+        (delta % self.step == 0 && delta / self.step >= 0)
+            .then(|| (delta / self.step) as usize)
+            .filter(|&level| level < self.num_levels())
     }
 }
 
@@ -35,47 +37,68 @@ fn solve() -> (usize, usize) {
         input.chars().position(|x| x == '\n').unwrap(),
         input.chars().filter(|x| *x == '\n').count(),
     ));
-    let map = Map::new(map_size, input.chars().filter(|&c| c != '\n').map(|b| b as u8 - b'0'));
+    let map = Map::new(
+        map_size,
+        input.chars().filter(|&c| c != '\n').map(|c| c.to_digit(36).map(|h| h as Height)),
+    );
 
-    let mut buf = Buf::sized::<{ 59 * 59 }>();
+    let ladder = Ladder { from: param("from", 0), to: param("to", 9), step: param("step", 1) };
+    assert!(
+        ladder.step != 0
+            && (ladder.to - ladder.from) % ladder.step == 0
+            && (ladder.to - ladder.from) / ladder.step >= 0,
+        "--step must lead from --from to --to"
+    );
 
-    map.iter()
-        .filter(|p| map[p] == 0)
-        .map(|p| dfs(p, &map, &mut buf))
-        .fold((0, 0), |acc, el| (acc.0 + el.0, acc.1 + el.1))
+    count_trails(&map, &ladder)
 }
 
-fn dfs(start: Pos, map: &Map<u8>, buf: &mut Buf) -> (usize, usize) {
-    buf.clear();
-    let mut res = 0;
+fn count_trails(map: &Map<Option<Height>>, ladder: &Ladder) -> (usize, usize) {
+    let level_of = |p: Pos| map.get(p).flatten().and_then(|h| ladder.level(h));
+    let index_of = |p: Pos| (p.y * map.size.x + p.x) as usize;
 
-    buf.queue.push_back(start);
+    // bucket the tiles by level, remembering each tile's slot within its bucket
+    let mut levels = vec![Vec::new(); ladder.num_levels()];
+    let mut slots = vec![usize::MAX; (map.size.x * map.size.y) as usize];
+    for pos in map.iter() {
+        if let Some(level) = level_of(pos) {
+            slots[index_of(pos)] = levels[level].len();
+            levels[level].push(pos);
+        }
+    }
 
-    while !buf.queue.is_empty() {
-        let cur = buf.queue.pop_front().unwrap();
-        let idx = (cur.y * map.size.x + cur.x) as usize;
-        buf.seen[idx] = true;
+    let summits = levels.last().unwrap().len();
+    let words = summits.div_ceil(64);
 
-        let cur_val = map[cur];
-        if cur_val == 9 {
-            res += 1;
-            buf.ends.insert(cur);
-            continue;
-        }
+    let mut reach = vec![0u64; summits * words];
+    for slot in 0..summits {
+        reach[slot * words + slot / 64] |= 1 << (slot % 64);
+    }
+    let mut ratings = vec![1usize; summits];
+
+    for level in (0..levels.len() - 1).rev() {
+        let tiles = &levels[level];
+        let mut new_reach = vec![0u64; tiles.len() * words];
+        let mut new_ratings = vec![0usize; tiles.len()];
 
-        buf.queue.extend(cur.neighbors_simple().filter_map(|n| {
-            map.get(n).and_then(|val| {
-                let n_idx = (n.y * map.size.x + n.x) as usize;
-                if val == cur_val + 1 && !buf.seen[n_idx] {
-                    Some(n)
-                } else {
-                    None
+        for (slot, &pos) in tiles.iter().enumerate() {
+            for next in pos.neighbors_simple().filter(|&n| level_of(n) == Some(level + 1)) {
+                let next_slot = slots[index_of(next)];
+                new_ratings[slot] += ratings[next_slot];
+                for w in 0..words {
+                    new_reach[slot * words + w] |= reach[next_slot * words + w];
                 }
-            })
-        }));
+            }
+        }
+
+        reach = new_reach;
+        ratings = new_ratings;
     }
 
-    (buf.ends.len(), res)
+    let p1 = reach.iter().map(|w| w.count_ones() as usize).sum();
+    let p2 = ratings.iter().sum();
+
+    (p1, p2)
 }
 
 aoc_2024::main! {
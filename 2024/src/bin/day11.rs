@@ -1,68 +1,165 @@
 //! # Plutonian Pebbles
 //!
-//! Shove the pebble tally in a HashMap and accumulate changes in a Vec, then
-//! apply them sequentially.
+//! Stones never interact, so the number of stones a single stone turns into after
+//! `n` blinks only depends on its value: memoize it per `(stone, n)` and sum up.
+//! Stones get dense ids so the memo is a small vector per stone instead of one
+//! huge hash table.
+//!
+//! The distribution of stone values after `n` blinks still needs the full tally:
+//! shove it in a HashMap and rebuild it on every blink.
+//!
+//! The blinking rules live behind the [`Rules`] trait. `--blinks N` reports the
+//! number of stones after `N` blinks, `--distribution` also lists every value.
 
-use aoc_prelude::{num_integer::Integer, ArrayVec, HashMap};
+use aoc_2024::{flag, param};
+use aoc_prelude::{num_integer::Integer, ArrayVec, HashMap, Itertools};
 
-type Int = i64;
-const TENS: [Int; 10] =
-    [1, 10, 100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000, 100_000_000, 1_000_000_000];
+type Stone = u128;
+type Count = u128;
 
-fn solve() -> (Int, Int) {
-    let mut tally: HashMap<Int, Int> = include_str!("../../inputs/11.in")
-        .split_ascii_whitespace()
-        .flat_map(str::parse::<Int>)
-        .map(|key| (key, 1))
-        .collect();
+trait Rules {
+    /// The stones that `stone` turns into after a single blink, at most two.
+    fn blink(&self, stone: Stone) -> ArrayVec<Stone, 2>;
+}
 
-    let mut changes = ArrayVec::<(Int, Int), 16384>::new();
+/// Zero becomes one, even number of digits splits in two, otherwise times 2024.
+struct Pluto;
 
-    for _ in 0..25 {
-        epoch(&mut tally, &mut changes);
+impl Rules for Pluto {
+    fn blink(&self, stone: Stone) -> ArrayVec<Stone, 2> {
+        if stone == 0 {
+            [1].into_iter().collect()
+        } else if let Some((left, right)) = split(stone) {
+            ArrayVec::from([left, right])
+        } else {
+            [stone.checked_mul(2024).expect("stone too large for a u128")].into_iter().collect()
+        }
     }
-    let p1: Int = tally.values().sum();
+}
+
+struct Blinker<R: Rules> {
+    rules: R,
+    /// Every stone value seen so far gets a dense id.
+    ids: HashMap<Stone, usize>,
+    stones: Vec<Stone>,
+    /// Ids of the stones each stone turns into, once worked out.
+    children: Vec<Option<ArrayVec<usize, 2>>>,
+    /// Stone counts per id and number of blinks, 0 when unknown.
+    memo: Vec<Vec<Count>>,
+}
 
-    for _ in 0..50 {
-        epoch(&mut tally, &mut changes);
+impl<R: Rules> Blinker<R> {
+    fn new(rules: R) -> Self {
+        Self {
+            rules,
+            ids: HashMap::with_capacity(1 << 12),
+            stones: Vec::with_capacity(1 << 12),
+            children: Vec::with_capacity(1 << 12),
+            memo: Vec::with_capacity(1 << 12),
+        }
     }
-    let p2: Int = tally.values().sum();
 
-    (p1, p2)
-}
+    /// Number of stones `stones` turn into after `blinks` blinks.
+    fn count(&mut self, stones: &[Stone], blinks: usize) -> Count {
+        stones.iter().fold(0, |acc: Count, &stone| {
+            let id = self.id(stone);
+            acc.checked_add(self.count_id(id, blinks)).expect("too many stones for a u128")
+        })
+    }
 
-fn num_digits(num: Int) -> u32 { num.ilog10() + 1 }
+    fn id(&mut self, stone: Stone) -> usize {
+        let next_id = self.stones.len();
+        let id = *self.ids.entry(stone).or_insert(next_id);
+        if id == next_id {
+            self.stones.push(stone);
+            self.children.push(None);
+            self.memo.push(Vec::new());
+        }
+        id
+    }
 
-fn split(num: Int) -> Option<(Int, Int)> {
-    let digits = num_digits(num);
-    let (q, r) = digits.div_rem(&2);
-    (r == 0).then(|| num.div_rem(&TENS[q as usize]))
-}
+    fn count_id(&mut self, id: usize, blinks: usize) -> Count {
+        if blinks == 0 {
+            return 1;
+        }
+        if let Some(&count) = self.memo[id].get(blinks).filter(|&&count| count > 0) {
+            return count;
+        }
 
-fn epoch(tally: &mut HashMap<Int, Int>, changes: &mut ArrayVec<(Int, Int), 16384>) {
-    changes.clear();
+        let children = match &self.children[id] {
+            Some(children) => children.clone(),
+            None => {
+                let children: ArrayVec<usize, 2> =
+                    self.rules.blink(self.stones[id]).iter().map(|&s| self.id(s)).collect();
+                self.children[id] = Some(children.clone());
+                children
+            }
+        };
+        let count = children.iter().fold(0, |acc: Count, &child| {
+            acc.checked_add(self.count_id(child, blinks - 1)).expect("too many stones for a u128")
+        });
 
-    // all zeroes become ones
-    if let Some(num_zeroes) = tally.remove(&0) {
-        changes.push((1, num_zeroes));
+        let memo = &mut self.memo[id];
+        if memo.len() <= blinks {
+            memo.resize(blinks + 1, 0);
+        }
+        memo[blinks] = count;
+        count
     }
 
-    for (&key, &val) in tally.iter() {
-        if val > 0 {
-            changes.push((key, -val));
+    /// How many stones of each value there are after `blinks` blinks.
+    fn distribution(&self, stones: &[Stone], blinks: usize) -> HashMap<Stone, Count> {
+        let mut tally = HashMap::with_capacity(stones.len());
+        for &stone in stones {
+            *tally.entry(stone).or_insert(0) += 1;
+        }
 
-            if let Some((left, right)) = split(key) {
-                changes.push((left, val));
-                changes.push((right, val));
-            } else {
-                changes.push((key * 2024, val));
+        for _ in 0..blinks {
+            let mut new_tally = HashMap::with_capacity(tally.len() * 2);
+            for (stone, count) in tally {
+                for new_stone in self.rules.blink(stone) {
+                    let total: &mut Count = new_tally.entry(new_stone).or_insert(0);
+                    *total = total.checked_add(count).expect("too many stones for a u128");
+                }
             }
+            tally = new_tally;
         }
+        tally
     }
+}
 
-    for (key, delta) in changes {
-        *tally.entry(*key).or_insert(0) += *delta;
+fn solve() -> (Count, Count) {
+    let stones = include_str!("../../inputs/11.in")
+        .split_ascii_whitespace()
+        .flat_map(str::parse::<Stone>)
+        .collect_vec();
+
+    let mut blinker = Blinker::new(Pluto);
+
+    if flag("blinks").is_some() {
+        let blinks: usize = param("blinks", 0);
+        let tally = blinker.distribution(&stones, blinks);
+        eprintln!("After {blinks} blinks: {} stones", blinker.count(&stones, blinks));
+        eprintln!("Distinct values: {}", tally.len());
+        if flag("distribution").is_some() {
+            for (stone, count) in tally.into_iter().sorted_unstable() {
+                eprintln!("{stone}: {count}");
+            }
+        }
     }
+
+    let p1 = blinker.count(&stones, 25);
+    let p2 = blinker.count(&stones, 75);
+
+    (p1, p2)
+}
+
+fn num_digits(num: Stone) -> u32 { num.ilog10() + 1 }
+
+fn split(num: Stone) -> Option<(Stone, Stone)> {
+    let digits = num_digits(num);
+    let (q, r) = digits.div_rem(&2);
+    (r == 0).then(|| num.div_rem(&Stone::pow(10, q)))
 }
 
 aoc_2024::main! {
@@ -1,188 +1,264 @@
 //! # Garden Groups
 //!
-//! Part 1: summing the number of fences for each block of every region and
-//! keeping track of its area is enough. We store the fences in an u8 and use
-//! bit shifting tricks.
+//! Label all the regions in one sweep with a BFS, then look at each of them in turn.
 //!
-//! Part 2: Ouuuffff, this was quite tedious:
-//! - implement an explorer that "hugs" the right wall of any region
-//! - the number of turns it takes before getting back to the initial state is
-//!   equal to the number of sides
-//! - run Explorers until the set of fenced tiles is empty
-//! - choose start direction so that we have a fence on our right
+//! Part 1: every tile contributes a fence for each neighbour outside of its region.
+//!
+//! Part 2: the number of sides of a polygon equals its number of corners, so for
+//! each tile check its four corners: it's convex if both orthogonal neighbours are
+//! outside the region, concave if both are inside but the diagonal one isn't.
+//!
+//! Holes are the pockets of foreign tiles in the region's bounding box that can't
+//! reach its border, and the outlines come from chaining the fence segments into
+//! closed loops (clockwise for the outer boundary, anticlockwise around holes).
+//! Neither is needed for the answer, so they're only worked out when asked for.
+//!
+//! `--diagonal` switches to 8-connected regions, `--regions` prints the stats of
+//! every region and `--svg` dumps the outlines as an SVG image.
 
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, VecDeque};
 
+use aoc_2024::flag;
 use aoc_2dmap::prelude::*;
-use aoc_prelude::HashSet;
+use aoc_prelude::Itertools;
 
-type Region = HashSet<Pos>;
+const SVG_SCALE: i32 = 10;
 
-type Dir = u8;
-
-const UP: Dir = 0b1000;
-const LEFT: Dir = 0b0100;
-const DOWN: Dir = 0b0010;
-const RIGHT: Dir = 0b0001;
+struct Region {
+    plant: char,
+    cells: Vec<Pos>,
+    area: usize,
+    perimeter: usize,
+    sides: usize,
+}
 
-trait DirHelper {
-    fn turn_right(self) -> Self;
-    fn turn_left(self) -> Self;
-    fn to_pos(self) -> Pos;
+/// Every region, along with which region each tile belongs to.
+struct Garden {
+    size: MapSize,
+    diagonal: bool,
+    labels: Vec<usize>,
+    regions: Vec<Region>,
 }
 
-impl DirHelper for Dir {
-    fn turn_right(self) -> Self {
-        match self {
-            UP => RIGHT,
-            x => x << 1,
+impl Garden {
+    fn inside(&self, label: usize) -> impl Fn(Pos) -> bool + '_ {
+        move |p: Pos| {
+            p.x >= 0
+                && p.y >= 0
+                && p.x < self.size.x
+                && p.y < self.size.y
+                && self.labels[(p.x + p.y * self.size.x) as usize] == label
         }
     }
 
-    fn turn_left(self) -> Self {
-        match self {
-            RIGHT => UP,
-            x => x >> 1,
-        }
+    /// Only needed for debugging, so it's worked out on demand.
+    fn holes(&self, label: usize) -> usize {
+        count_holes(&self.regions[label].cells, self.inside(label), !self.diagonal)
     }
 
-    fn to_pos(self) -> Pos {
-        match self {
-            UP => NORTH,
-            DOWN => SOUTH,
-            LEFT => WEST,
-            RIGHT => EAST,
-            _ => unreachable!(),
-        }
+    fn outline(&self, label: usize) -> Vec<Vec<Pos>> {
+        outline(&self.regions[label].cells, self.inside(label), self.diagonal)
     }
 }
 
-#[derive(Copy, Clone)]
-struct Tile {
-    ch: char,
-    fences: u8, // UDLR
-    start_dir: u8,
-}
+fn solve() -> (usize, usize) {
+    let input = include_str!("../../inputs/12.in");
+    let map_size = Pos::from((
+        input.chars().position(|x| x == '\n').unwrap(),
+        input.chars().filter(|x| *x == '\n').count(),
+    ));
+    let map = Map::new(map_size, input.chars().filter(|&c| c != '\n'));
 
-impl From<char> for Tile {
-    fn from(value: char) -> Self { Self { ch: value, fences: 0, start_dir: 0 } }
-}
+    let garden = analyze(&map, flag("diagonal").is_some());
+    let regions = &garden.regions;
 
-impl Tile {
-    fn has_fence(&self, dir: Dir) -> bool { self.fences & dir == dir }
-}
+    if flag("regions").is_some() {
+        for (label, r) in regions.iter().enumerate() {
+            eprintln!(
+                "{}: area {}, perimeter {}, sides {}, holes {}",
+                r.plant,
+                r.area,
+                r.perimeter,
+                r.sides,
+                garden.holes(label)
+            );
+        }
+    }
+    if flag("svg").is_some() {
+        eprintln!("{}", to_svg(&garden));
+    }
+
+    let p1 = regions.iter().map(|r| r.area * r.perimeter).sum();
+    let p2 = regions.iter().map(|r| r.area * r.sides).sum();
 
-struct Explorer<'a> {
-    pos: Pos,
-    dir: Dir,
-    map: &'a Map<Tile>,
-    initial: (Pos, Dir),
+    (p1, p2)
 }
 
-impl<'a> Explorer<'a> {
-    fn new(map: &'a Map<Tile>, start_pos: Pos, dir: Dir) -> Self {
-        Self { pos: start_pos, dir, map, initial: (start_pos, dir) }
-    }
+fn analyze(map: &Map<char>, diagonal: bool) -> Garden {
+    let index_of = |p: Pos| (p.x + p.y * map.size.x) as usize;
 
-    fn sides<F: FnMut(Pos)>(&mut self, mut visit: F) -> usize {
-        let mut turns = 0;
-        loop {
-            visit(self.pos);
+    let mut labels = vec![usize::MAX; (map.size.x * map.size.y) as usize];
+    let mut q = VecDeque::with_capacity(512);
+    let mut regions = Vec::new();
 
-            if turns > 0 && (self.pos, self.dir) == self.initial {
-                break;
-            }
+    for pos in map.iter() {
+        if labels[index_of(pos)] != usize::MAX {
+            continue;
+        }
 
-            let cur_tile = self.map[self.pos];
+        let label = regions.len();
+        let crop = map[pos];
+        let mut cells = Vec::new();
 
-            // try to turn right and advance
-            let right = self.dir.turn_right();
-            if !cur_tile.has_fence(right) {
-                turns += 1;
-                self.dir = right;
-                self.pos += self.dir.to_pos();
-                continue;
-            }
+        labels[index_of(pos)] = label;
+        q.push_back(pos);
 
-            // try to advance
-            if !cur_tile.has_fence(self.dir) {
-                self.pos += self.dir.to_pos();
-                continue;
+        while let Some(cur) = q.pop_front() {
+            cells.push(cur);
+            for neigh in neighbors(cur, diagonal) {
+                if map.get(neigh) == Some(crop) && labels[index_of(neigh)] == usize::MAX {
+                    labels[index_of(neigh)] = label;
+                    q.push_back(neigh);
+                }
             }
-
-            // try to turn left
-            self.dir = self.dir.turn_left();
-            turns += 1;
         }
-        turns
+
+        let inside = |p: Pos| map.within(p) && labels[index_of(p)] == label;
+        regions.push(Region {
+            plant: crop,
+            area: cells.len(),
+            perimeter: cells
+                .iter()
+                .map(|&c| ORTHOGONAL.iter().filter(|&&d| !inside(c + d)).count())
+                .sum(),
+            sides: cells.iter().map(|&c| corners(c, inside)).sum(),
+            cells,
+        });
     }
+
+    Garden { size: map.size, diagonal, labels, regions }
 }
 
-fn solve() -> (usize, usize) {
-    let input = include_str!("../../inputs/12.in");
-    let map_size = Pos::from((
-        input.chars().position(|x| x == '\n').unwrap(),
-        input.chars().filter(|x| *x == '\n').count(),
-    ));
-    let mut map = Map::new(map_size, input.chars().filter(|&c| c != '\n').map(Tile::from));
+fn neighbors(pos: Pos, diagonal: bool) -> impl Iterator<Item = Pos> {
+    pos.neighbors_simple().chain(diagonal.then(|| pos.neighbors_only_diag()).into_iter().flatten())
+}
 
-    let mut seen = vec![false; (map.size.x * map.size.y) as usize];
-    let mut fenced = Region::with_capacity(1024);
-    let mut q = VecDeque::with_capacity(512);
+fn corners(pos: Pos, inside: impl Fn(Pos) -> bool) -> usize {
+    ORTHOGONAL
+        .iter()
+        .map(|&d| (d, d.clockwise()))
+        .filter(|&(d1, d2)| {
+            let (a, b) = (inside(pos + d1), inside(pos + d2));
+            (!a && !b) || (a && b && !inside(pos + d1 + d2))
+        })
+        .count()
+}
+
+/// Foreign pockets inside the bounding box (padded by one) which can't reach its
+/// edge. `diagonal` should be the opposite of the region's own connectivity.
+fn count_holes(cells: &[Pos], inside: impl Fn(Pos) -> bool, diagonal: bool) -> usize {
+    let (min_x, max_x) = cells.iter().map(|p| p.x).minmax().into_option().unwrap();
+    let (min_y, max_y) = cells.iter().map(|p| p.y).minmax().into_option().unwrap();
+    let (lo, hi) = (Pos::new(min_x - 1, min_y - 1), Pos::new(max_x + 1, max_y + 1));
 
-    let index_of = |p: Pos| (p.x + p.y * map_size.x) as usize;
+    let width = hi.x - lo.x + 1;
+    let index_of = |p: Pos| ((p.y - lo.y) * width + p.x - lo.x) as usize;
+    let in_box = |p: Pos| p.x >= lo.x && p.x <= hi.x && p.y >= lo.y && p.y <= hi.y;
 
-    let mut p1 = 0;
-    let mut p2 = 0;
+    let mut seen = vec![false; (width * (hi.y - lo.y + 1)) as usize];
+    let mut q = VecDeque::new();
+    let mut pockets = 0;
 
-    for pos in map_size.iter() {
-        if seen[index_of(pos)] {
+    for start in (lo.y..=hi.y).flat_map(|y| (lo.x..=hi.x).map(move |x| Pos::new(x, y))) {
+        if seen[index_of(start)] || inside(start) {
             continue;
         }
-
-        let mut area = 0;
-        let mut perimeter = 0;
-        fenced.clear();
-
-        seen[index_of(pos)] = true;
-        q.clear();
-        q.push_back(pos);
-
+        pockets += 1;
+        seen[index_of(start)] = true;
+        q.push_back(start);
         while let Some(cur) = q.pop_front() {
-            // R,D,L,U
-            let crop = map[cur].ch;
-
-            for (dir, neigh) in cur.neighbors_rdlu().enumerate() {
-                if !map.within(neigh) || map[neigh].ch != crop {
-                    let tile = &mut map[cur];
-                    let fence = 1 << dir;
-                    tile.fences |= fence;
-                    tile.start_dir = fence.turn_left();
-                    fenced.insert(cur);
-                    perimeter += 1;
-                } else if !seen[index_of(neigh)] {
+            for neigh in neighbors(cur, diagonal) {
+                if in_box(neigh) && !seen[index_of(neigh)] && !inside(neigh) {
                     seen[index_of(neigh)] = true;
                     q.push_back(neigh);
                 }
             }
-            area += 1;
         }
-        p1 += area * perimeter;
-        p2 += area * count_sides(&map, &mut fenced);
     }
-    (p1, p2)
+
+    // the padding guarantees the outside is a single pocket
+    pockets - 1
 }
 
-fn count_sides(map: &Map<Tile>, fenced: &mut Region) -> usize {
-    let mut sides = 0;
-    while !fenced.is_empty() {
-        let start_pos = *fenced.iter().next().unwrap();
-        let start_dir = map[start_pos].start_dir;
-        sides += Explorer::new(map, start_pos, start_dir).sides(|pos| {
-            fenced.remove(&pos);
-        });
+/// Closed polygons (lattice corners) around the region, keeping it on the right.
+fn outline(cells: &[Pos], inside: impl Fn(Pos) -> bool, diagonal: bool) -> Vec<Vec<Pos>> {
+    // fence segments as (start corner, direction)
+    let mut edges = BTreeSet::new();
+    for &cell in cells {
+        for (dir, corner) in [(EAST, (0, 0)), (SOUTH, (1, 0)), (WEST, (1, 1)), (NORTH, (0, 1))] {
+            if !inside(cell + dir.anticlockwise()) {
+                edges.insert((cell + Pos::from(corner), dir));
+            }
+        }
+    }
+
+    let mut polygons = Vec::new();
+    while let Some((start, start_dir)) = edges.pop_first() {
+        let mut vertices = Vec::new();
+        let (mut cur, mut dir) = (start + start_dir, start_dir);
+
+        loop {
+            // where two corners touch, 4-connected regions turn back towards themselves
+            // while 8-connected ones carry on into the diagonal tile
+            let turns = match diagonal {
+                true => [dir.anticlockwise(), dir, dir.clockwise()],
+                false => [dir.clockwise(), dir, dir.anticlockwise()],
+            };
+            let next_dir = turns
+                .into_iter()
+                .find(|&d| edges.contains(&(cur, d)) || (cur, d) == (start, start_dir))
+                .expect("fences form closed loops");
+
+            if next_dir != dir {
+                vertices.push(cur);
+            }
+            if (cur, next_dir) == (start, start_dir) {
+                break;
+            }
+            edges.remove(&(cur, next_dir));
+            (cur, dir) = (cur + next_dir, next_dir);
+        }
+        polygons.push(vertices);
+    }
+    polygons
+}
+
+fn to_svg(garden: &Garden) -> String {
+    let map_size = garden.size;
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        map_size.x * SVG_SCALE,
+        map_size.y * SVG_SCALE,
+        map_size.x,
+        map_size.y
+    );
+    for (label, r) in garden.regions.iter().enumerate() {
+        let path = garden
+            .outline(label)
+            .iter()
+            .map(|poly| format!("M{}Z", poly.iter().map(|p| format!("{} {}", p.x, p.y)).join(" L")))
+            .join(" ");
+        let hue = (r.plant as u32 * 47) % 360;
+        svg += &format!(
+            r#"<path d="{path}" fill="hsl({hue},60%,70%)" fill-rule="evenodd" stroke="black" stroke-width="0.05"><title>{}: area {}, perimeter {}, sides {}, holes {}</title></path>"#,
+            r.plant,
+            r.area,
+            r.perimeter,
+            r.sides,
+            garden.holes(label)
+        );
     }
-    sides
+    svg + "</svg>"
 }
 
 aoc_2024::main! {
@@ -1,13 +1,34 @@
 //! # Claw Contraption
 //!
-//! Friday difficulty: solve systems of two equations using substitution.
+//! Friday difficulty: solve systems of two equations using Cramer's rule, keeping
+//! only the solutions with whole, non-negative button presses.
+//!
+//! When the buttons are collinear the determinant is zero and there may be many
+//! solutions along the line: the presses then satisfy `a * p + b * q = t` along a
+//! single axis, which extended Euclid solves as `a = a0 + k * q/g, b = b0 - k * p/g`.
+//! The cost is linear in `k`, so the cheapest combination sits at one end of the
+//! range of `k` for which both presses stay non-negative.
+//!
+//! Token costs and the prize offset of part 2 are configurable through
+//! `--cost-a 3 --cost-b 1 --offset 10000000000000`.
 
-type Int = i64;
+type Int = i128;
 type Pair = (Int, Int);
 
+use aoc_2024::param;
 use aoc_prelude::num_integer::Integer;
 
+/// Tokens it takes to press each button once.
+struct Costs {
+    a: Int,
+    b: Int,
+}
+
 fn solve() -> (Int, Int) {
+    let costs = Costs { a: param("cost-a", 3), b: param("cost-b", 1) };
+    assert!(costs.a >= 0 && costs.b >= 0, "button costs can't be negative");
+    let offset: Int = param("offset", 10000000000000);
+
     let (p1, p2) = include_str!("../../inputs/13.in")
         .split("\n\n")
         .filter_map(|lines| {
@@ -16,8 +37,9 @@ fn solve() -> (Int, Int) {
             let e1 = extract_pair(lines.next()?)?;
             let r = extract_pair(lines.next()?)?;
             Some((
-                solve_eq(e0, e1, r).map(token_total),
-                solve_eq(e0, e1, (r.0 + 10000000000000, r.1 + 10000000000000)).map(token_total),
+                solve_eq(e0, e1, r, &costs).map(|presses| token_total(presses, &costs)),
+                solve_eq(e0, e1, (r.0 + offset, r.1 + offset), &costs)
+                    .map(|presses| token_total(presses, &costs)),
             ))
         })
         .fold((0, 0), |acc, res| (acc.0 + res.0.unwrap_or(0), acc.1 + res.1.unwrap_or(0)));
@@ -31,14 +53,71 @@ fn extract_pair(s: &str) -> Option<Pair> {
     Some((it.next()?, it.next()?))
 }
 
-#[inline]
-fn solve_eq(e0: Pair, e1: Pair, r: Pair) -> Option<Pair> {
-    let (a, rem) = (e1.1 * r.0 - e1.0 * r.1).div_rem(&(e1.1 * e0.0 - e0.1 * e1.0));
-    (rem == 0).then(|| (a, (r.0 - e0.0 * a) / e1.0))
+/// Cheapest number of presses of each button that lands the claw on `r`.
+fn solve_eq(e0: Pair, e1: Pair, r: Pair, costs: &Costs) -> Option<Pair> {
+    let det = e0.0 * e1.1 - e0.1 * e1.0;
+    if det != 0 {
+        let (a, rem_a) = (r.0 * e1.1 - r.1 * e1.0).div_rem(&det);
+        let (b, rem_b) = (e0.0 * r.1 - e0.1 * r.0).div_rem(&det);
+        return (rem_a == 0 && rem_b == 0 && a >= 0 && b >= 0).then_some((a, b));
+    }
+
+    // collinear buttons: solve along an axis one of them moves on, then check the
+    // prize actually lies on their line
+    let (a, b) = match e0.0 != 0 || e1.0 != 0 {
+        true => solve_line(e0.0, e1.0, r.0, costs)?,
+        false => solve_line(e0.1, e1.1, r.1, costs)?,
+    };
+    (a * e0.0 + b * e1.0 == r.0 && a * e0.1 + b * e1.1 == r.1).then_some((a, b))
+}
+
+/// Cheapest non-negative solution of `a * p + b * q = t`.
+fn solve_line(p: Int, q: Int, t: Int, costs: &Costs) -> Option<Pair> {
+    if p == 0 && q == 0 {
+        return (t == 0).then_some((0, 0));
+    }
+
+    let egcd = p.extended_gcd(&q);
+    let (k, rem) = t.div_rem(&egcd.gcd);
+    if rem != 0 {
+        return None;
+    }
+    let (a0, b0) = (egcd.x * k, egcd.y * k);
+    let (da, db) = (q / egcd.gcd, -p / egcd.gcd);
+
+    // range of k for which a0 + k * da >= 0 and b0 + k * db >= 0
+    let (mut lo, mut hi) = (None, None);
+    for (base, step) in [(a0, da), (b0, db)] {
+        match step.signum() {
+            1 => lo = lo.max(Some(-Integer::div_floor(&base, &step))),
+            -1 => {
+                let bound = Integer::div_floor(&base, &-step);
+                hi = Some(hi.map_or(bound, |hi: Int| hi.min(bound)));
+            }
+            _ if base < 0 => return None,
+            _ => {}
+        }
+    }
+    if let (Some(lo), Some(hi)) = (lo, hi) {
+        if lo > hi {
+            return None;
+        }
+    }
+
+    // the cost is linear in k, so the cheapest end of the range wins
+    let slope = costs.a * da + costs.b * db;
+    let k = match slope.signum() {
+        1 => lo,
+        -1 => hi,
+        _ => lo.or(hi),
+    }
+    .unwrap_or(0);
+
+    Some((a0 + k * da, b0 + k * db))
 }
 
 #[inline]
-fn token_total((a, b): Pair) -> Int { a * 3 + b }
+fn token_total((a, b): Pair, costs: &Costs) -> Int { a * costs.a + b * costs.b }
 
 aoc_2024::main! {
     solve()
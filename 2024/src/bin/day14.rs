@@ -1,49 +1,106 @@
 //! # Restroom Redoubt
 //!
-//! Part 1: simple modulo arithmetics.
+//! Part 1: simple modulo arithmetics. On odd sized arenas the robots on the middle
+//! row or column don't belong to any quadrant, on even ones the halves just touch.
 //!
-//! Part 2: detected a cycle, inspected the output manually, and noticed
-//! the Pine Tree is surrounded by a frame of bots.
+//! Part 2: the robots move independently along each axis, so their x coordinates
+//! repeat every `width` seconds and their y coordinates every `height` seconds.
+//! When they gather into a picture, the spread of both coordinates drops well
+//! below its usual value: look for the second with the lowest variance of x within
+//! one x period and likewise for y, then stitch the two together with the Chinese
+//! Remainder Theorem. This doesn't care what the picture looks like, only that
+//! it's more compact than random noise.
 //!
-//! Used a `u16` bit set to store the robot positions, and on each store
-//! we check if the shard is equal to `u16::MAX` which means we have
-//! 16 bots in a row, which means we found the frame.
+//! The confidence is how many standard deviations each minimum sits below the
+//! average variance over its period (the lower of the two). Random noise barely
+//! reaches 4, the Christmas tree sits way beyond that: below it there's no picture
+//! and part 2 is 0. `--stats` prints it.
 //!
-//! Sprinkle rayon + atomics for parallel search.
-
-use std::{
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
-    thread::available_parallelism,
-};
+//! The arena is 101x103 unless told otherwise with `--size 11x7`, and
+//! `--render <second>` prints the arena at that second as `--format ascii|pbm|pgm`.
 
+use aoc_2024::{flag, param};
 use aoc_2dmap::prelude::Pos;
 use aoc_prelude::{num_integer::Integer, Itertools};
-use rayon::prelude::*;
 
-const MAP_SIZE: Pos = Pos::c_new(101, 103);
-const MID: Pos = Pos::c_new(50, 51);
-const CYCLE_LEN: usize = 101 * 103;
-const SET_SIZE: usize = ((MAP_SIZE.x * MAP_SIZE.y) as usize / 16) + 1;
+/// Confidence below which the quietest second is just random noise.
+const MIN_CONFIDENCE: f64 = 4.0;
 
-struct BitSet {
-    inner: [u16; SET_SIZE],
+struct Arena {
+    size: Pos,
 }
 
-impl Default for BitSet {
-    fn default() -> Self { Self { inner: [0; SET_SIZE] } }
-}
+impl Arena {
+    fn parse(s: &str) -> Self {
+        let (x, y) = s
+            .split('x')
+            .map(|n| n.parse().expect("--size should look like 101x103"))
+            .collect_tuple()
+            .expect("--size should look like 101x103");
+        assert!(x > 0 && y > 0, "the arena can't be empty");
+        Self { size: Pos::new(x, y) }
+    }
+
+    fn fast_forward_pos(&self, pos: Pos, speed: Pos, turns: usize) -> Pos {
+        let (tx, ty) =
+            ((turns % self.size.x as usize) as i32, (turns % self.size.y as usize) as i32);
+        Pos::new(
+            (pos.x + speed.x * tx).rem_euclid(self.size.x),
+            (pos.y + speed.y * ty).rem_euclid(self.size.y),
+        )
+    }
 
-impl BitSet {
-    // returns true if the shard becomes all ones
-    fn set(&mut self, p: &Pos) -> bool {
-        let idx = (p.y * MAP_SIZE.x + p.x) as usize;
-        let (shard, shift) = idx.div_rem(&16);
-        let word = &mut self.inner[shard];
-        *word |= 1 << shift;
-        *word == u16::MAX
+    fn safety_factor(&self, robots: impl Iterator<Item = Pos>) -> usize {
+        // lower half is below `size / 2`, upper half from `(size + 1) / 2` on
+        let half = |c: i32, len: i32| match c {
+            c if c < len / 2 => Some(0),
+            c if c >= (len + 1) / 2 => Some(1),
+            _ => None,
+        };
+        let mut quads = [0, 0, 0, 0];
+        robots.for_each(|bot| {
+            if let (Some(qx), Some(qy)) = (half(bot.x, self.size.x), half(bot.y, self.size.y)) {
+                quads[qy * 2 + qx] += 1;
+            }
+        });
+        quads.iter().product()
     }
 
-    fn clear(&mut self) { self.inner.fill(0); }
+    /// Number of robots on each tile, row by row.
+    fn counts(&self, robots: impl Iterator<Item = Pos>) -> Vec<usize> {
+        let mut counts = vec![0; (self.size.x * self.size.y) as usize];
+        robots.for_each(|bot| counts[(bot.y * self.size.x + bot.x) as usize] += 1);
+        counts
+    }
+
+    fn render(&self, robots: impl Iterator<Item = Pos>, format: &str) -> String {
+        let counts = self.counts(robots);
+        let rows = counts.chunks(self.size.x as usize);
+        let (w, h) = (self.size.x, self.size.y);
+        match format {
+            "ascii" => rows
+                .map(|row| {
+                    row.iter()
+                        .map(|&c| match c {
+                            0 => '.',
+                            1..=9 => char::from_digit(c as u32, 10).unwrap(),
+                            _ => '+',
+                        })
+                        .collect::<String>()
+                })
+                .join("\n"),
+            "pbm" => format!(
+                "P1\n{w} {h}\n{}",
+                rows.map(|row| row.iter().map(|&c| (c > 0) as u8).join(" ")).join("\n")
+            ),
+            "pgm" => format!(
+                "P2\n{w} {h}\n{}\n{}",
+                counts.iter().max().unwrap().max(&1),
+                rows.map(|row| row.iter().join(" ")).join("\n")
+            ),
+            _ => panic!("unknown format: {format}"),
+        }
+    }
 }
 
 fn solve() -> (usize, usize) {
@@ -63,48 +120,76 @@ fn solve() -> (usize, usize) {
             speeds.push(Pos::new(sx, sy));
         });
 
-    let p1 = into_quadrants(
-        robots.iter().enumerate().map(|(idx, pos)| fast_forward_pos(*pos, speeds[idx], 100)),
-    );
-
-    let num_threads = available_parallelism().unwrap().get().max(32);
-    let found = AtomicBool::new(false);
-    let p2 = AtomicUsize::new(0);
-
-    (0..num_threads).into_par_iter().for_each(|offset| {
-        let mut bit_set = BitSet::default();
-        let mut i = 0;
-        loop {
-            let check = i * num_threads + offset;
-            if check > CYCLE_LEN || found.load(Ordering::Relaxed) {
-                return;
-            }
-            for (rob_idx, rob) in robots.iter().enumerate() {
-                if bit_set.set(&fast_forward_pos(*rob, speeds[rob_idx], check as i32)) {
-                    p2.store(check, Ordering::Relaxed);
-                    found.store(true, Ordering::Relaxed);
-                    return;
-                }
-            }
-            i += 1;
-            bit_set.clear();
+    let arena = &Arena::parse(&flag("size").unwrap_or("101x103".into()));
+    let at = |turns: usize| {
+        robots
+            .iter()
+            .zip(&speeds)
+            .map(move |(&pos, &speed)| arena.fast_forward_pos(pos, speed, turns))
+    };
+
+    if let Some(second) = flag("render") {
+        let second = second.parse().expect("--render needs a number of seconds");
+        eprintln!("{}", arena.render(at(second), &param("format", "ascii".to_string())));
+    }
+
+    let p1 = arena.safety_factor(at(100));
+
+    let (tx, score_x) = quietest(arena.size.x as usize, |t| at(t).map(|p| p.x));
+    let (ty, score_y) = quietest(arena.size.y as usize, |t| at(t).map(|p| p.y));
+    let confidence = score_x.min(score_y);
+    let second = crt((tx, arena.size.x as usize), (ty, arena.size.y as usize));
+    let p2 = second.filter(|_| confidence >= MIN_CONFIDENCE);
+
+    if flag("stats").is_some() {
+        eprintln!("x period: second {tx}, {score_x:.1} standard deviations below average");
+        eprintln!("y period: second {ty}, {score_y:.1} standard deviations below average");
+        match (second, p2) {
+            (Some(t), Some(_)) => eprintln!("picture at second {t}, confidence {confidence:.1}"),
+            (Some(t), None) => eprintln!(
+                "no picture, the quietest second {t} only has confidence {confidence:.1} \
+                 (below {MIN_CONFIDENCE})"
+            ),
+            (None, _) => eprintln!("the two periods don't agree on any second"),
         }
-    });
+    }
 
-    (p1, p2.load(Ordering::Relaxed))
+    (p1, p2.unwrap_or(0))
 }
 
-fn into_quadrants(robots: impl Iterator<Item = Pos>) -> usize {
-    let mut quads = [0, 0, 0, 0];
-    robots.for_each(|bot| quads[((bot.y < MID.y) as usize * 2) + (bot.x < MID.x) as usize] += 1);
-    quads.iter().product()
+/// Second within `period` where the coordinates are the least spread out, along
+/// with how unusual that is (in standard deviations).
+fn quietest<I: Iterator<Item = i32>>(period: usize, coords: impl Fn(usize) -> I) -> (usize, f64) {
+    let variances = (0..period)
+        .map(|t| {
+            let (n, sum, sum_sq) = coords(t).fold((0i64, 0i64, 0i64), |(n, sum, sum_sq), c| {
+                (n + 1, sum + c as i64, sum_sq + (c as i64).pow(2))
+            });
+            // n² times the variance, which keeps it in integers
+            (n * sum_sq - sum * sum) as f64
+        })
+        .collect_vec();
+
+    let (best, &min) = variances.iter().enumerate().min_by(|a, b| a.1.total_cmp(b.1)).unwrap();
+    let mean = variances.iter().sum::<f64>() / period as f64;
+    let std_dev =
+        (variances.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / period as f64).sqrt();
+    let score = if std_dev > 0.0 { (mean - min) / std_dev } else { 0.0 };
+
+    (best, score)
 }
 
-fn fast_forward_pos(pos: Pos, speed: Pos, turns: i32) -> Pos {
-    Pos::new(
-        (pos.x + speed.x * turns).rem_euclid(MAP_SIZE.x),
-        (pos.y + speed.y * turns).rem_euclid(MAP_SIZE.y),
-    )
+/// Smallest `t` with `t % m1 == r1` and `t % m2 == r2`, if there is one. Every
+/// robot is back where it started after `lcm(m1, m2)` seconds, so that's unique.
+fn crt((r1, m1): (usize, usize), (r2, m2): (usize, usize)) -> Option<usize> {
+    let (m1, m2, r1, r2) = (m1 as i64, m2 as i64, r1 as i64, r2 as i64);
+    let egcd = m1.extended_gcd(&m2);
+    let (k, rem) = (r2 - r1).div_rem(&egcd.gcd);
+    if rem != 0 {
+        return None;
+    }
+    let lcm = m1 / egcd.gcd * m2;
+    Some((r1 + m1 * (k * egcd.x).rem_euclid(m2 / egcd.gcd)).rem_euclid(lcm) as usize)
 }
 
 aoc_2024::main! {
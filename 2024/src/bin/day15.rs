@@ -1,137 +1,259 @@
 //! # Warehouse Woes
 //!
-//! Part 2: Look at a window of 4 tiles above or below the starting position
-//! and check whether we need to add any boxes to the push set.
+//! Part 2: flood the push set from the robot in the direction of the move, pulling
+//! in the whole box whenever we hit any part of one. Boxes are `O` when they're one
+//! tile wide and `[`, `=`..., `]` when wider, so `--width N` widens the map by any
+//! factor (2 by default).
+//!
+//! There may be several robots, each with its own moves in a separate paragraph.
+//! They take turns in reading order, one move each, and stay put once they're out
+//! of moves. Robots can't push each other: another robot behaves like a wall.
+//!
+//! The [`Warehouse`] moves the robots one step at a time and logs the tiles each
+//! move overwrote, so any move can be taken back. `--part 1|2` picks the warehouse
+//! to debug (part 2 by default), `--replay` prints it after every move and
+//! `--undo N` prints it as it was `N` moves before the end.
 
 use std::collections::VecDeque;
 
+use aoc_2024::{flag, param};
 use aoc_2dmap::prelude::*;
 use aoc_prelude::HashSet;
 
-const ZERO: Pos = Pos::c_new(0, 0);
+const DIR_CHARS: [char; 4] = ['^', '>', 'v', '<'];
+
+/// A single robot move, with enough information to take it back.
+struct Move {
+    bot: usize,
+    dir: Pos,
+    from: Pos,
+    /// Tiles overwritten by the move, with their previous contents.
+    overwritten: Vec<(Pos, char)>,
+}
 
-struct Buf {
+struct Warehouse {
     map: Map<char>,
-    changes: Vec<(Pos, char)>,
+    bots: Vec<Pos>,
+    log: Vec<Move>,
     push_set: HashSet<Pos>,
     queue: VecDeque<Pos>,
 }
 
 fn solve() -> (i32, i32) {
-    let (map, dirs) = include_str!("../../inputs/15.in").split_once("\n\n").unwrap();
+    let (map, moves) = include_str!("../../inputs/15.in").split_once("\n\n").unwrap();
+    let moves = moves
+        .split("\n\n")
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| parse_moves(s).unwrap_or_else(|err| panic!("{err}")))
+        .collect::<Vec<_>>();
 
     let map_size = Pos::from((
         map.chars().position(|x| x == '\n').unwrap(),
         map.chars().filter(|x| *x == '\n').count() + 1, // .split_once ate my ending newline
     ));
 
-    let mut p1_map = Map::new(map_size, map.chars().filter(|&c| c != '\n'));
-    let p1_bot = find_bot(&p1_map);
-
-    let mut p2_map = Map::fill((2 * p1_map.size.x, p1_map.size.y), '.');
-    for pos in p1_map.iter() {
-        let (tl, tr) = match p1_map[pos] {
-            '#' => ('#', '#'),
-            'O' => ('[', ']'),
-            '.' => ('.', '.'),
-            '@' => ('@', '.'),
-            _ => continue,
-        };
-        p2_map[Pos::new(2 * pos.x, pos.y)] = tl;
-        p2_map[Pos::new(2 * pos.x + 1, pos.y)] = tr;
-    }
-    let p2_bot = find_bot(&p2_map);
+    let p1_map = Map::new(map_size, map.chars().filter(|&c| c != '\n'));
+    let p2_map = widen(&p1_map, param("width", 2));
 
-    p1_map[p1_bot] = '.';
-    p2_map[p2_bot] = '.';
+    let mut warehouses = [Warehouse::new(p1_map), Warehouse::new(p2_map)];
+    let debug = param("part", 2) - 1;
+    assert_eq!(warehouses[0].bots.len(), moves.len(), "every robot needs its own moves");
 
-    let mut buf = Buf {
-        map: p1_map,
-        changes: Vec::with_capacity(512),
-        push_set: HashSet::with_capacity(512),
-        queue: VecDeque::with_capacity(10),
-    };
+    let longest = moves.iter().map(Vec::len).max().unwrap_or(0);
+    let turns = (0..longest)
+        .flat_map(|turn| {
+            let moves = &moves;
+            (0..moves.len()).filter_map(move |bot| moves[bot].get(turn).map(|&dir| (bot, dir)))
+        })
+        .collect::<Vec<_>>();
 
-    walk(dirs, p1_bot, &mut buf);
-    let p1 = tally(&buf.map, 'O');
+    for (idx, warehouse) in warehouses.iter_mut().enumerate() {
+        for (step, &(bot, dir)) in turns.iter().enumerate() {
+            let moved = warehouse.step(bot, dir);
+            if idx == debug && flag("replay").is_some() {
+                let blocked = if moved { "" } else { " (blocked)" };
+                eprintln!(
+                    "Move {}/{}: robot {} {}{blocked}\n{}\n",
+                    step + 1,
+                    turns.len(),
+                    bot + 1,
+                    dir_to_ch(dir),
+                    warehouse.render()
+                );
+            }
+        }
+    }
 
-    buf.map = p2_map;
-    walk(dirs, p2_bot, &mut buf);
-    let p2 = tally(&buf.map, '[');
+    let p1 = warehouses[0].tally();
+    let p2 = warehouses[1].tally();
+
+    if let Some(moves) = flag("undo") {
+        let moves = moves.parse().expect("--undo needs a number of moves");
+        let warehouse = &mut warehouses[debug];
+        let undone: String =
+            (0..moves).map_while(|_| warehouse.undo()).map(|mv| dir_to_ch(mv.dir)).collect();
+        eprintln!(
+            "Took back {}\nAfter {} moves:\n{}",
+            undone,
+            warehouse.log.len(),
+            warehouse.render()
+        );
+    }
 
     (p1, p2)
 }
 
-fn ch_to_dir(c: char) -> Pos {
-    match c {
-        '^' => NORTH,
-        '>' => EAST,
-        'v' => SOUTH,
-        '<' => WEST,
-        _ => ZERO,
+/// Stretches every tile `factor` times horizontally. Boxes stretch into a single
+/// wider box, robots stay one tile wide.
+fn widen(map: &Map<char>, factor: i32) -> Map<char> {
+    assert!(factor > 0, "--width must be positive");
+    let mut wide = Map::fill((factor * map.size.x, map.size.y), '.');
+    for pos in map.iter() {
+        let left = Pos::new(factor * pos.x, pos.y);
+        for dx in 0..factor {
+            wide[left + Pos::new(dx, 0)] = match map[pos] {
+                'O' if factor == 1 => 'O',
+                'O' if dx == 0 => '[',
+                'O' if dx == factor - 1 => ']',
+                'O' => '=',
+                '@' if dx > 0 => '.',
+                tile => tile,
+            };
+        }
     }
+    wide
 }
 
-fn walk(dirs: &str, start_pos: Pos, buf: &mut Buf) {
-    let mut bot = start_pos;
+/// The robot's moves, ignoring line breaks.
+fn parse_moves(s: &str) -> Result<Vec<Pos>, String> {
+    s.lines()
+        .enumerate()
+        .flat_map(|(row, line)| line.chars().enumerate().map(move |(col, c)| (row, col, c)))
+        .filter(|(_, _, c)| !c.is_whitespace())
+        .map(|(row, col, c)| {
+            ch_to_dir(c).ok_or_else(|| {
+                format!("invalid move {c:?} on line {}, column {} of the moves", row + 1, col + 1)
+            })
+        })
+        .collect()
+}
 
-    for ch in dirs.chars() {
-        let dxy = ch_to_dir(ch);
+fn ch_to_dir(c: char) -> Option<Pos> {
+    DIR_CHARS.iter().position(|&d| d == c).map(|idx| ORTHOGONAL[idx])
+}
 
-        let dest = bot + dxy;
-        let tile = buf.map[dest];
+fn dir_to_ch(dir: Pos) -> char { DIR_CHARS[ORTHOGONAL.iter().position(|&d| d == dir).unwrap()] }
 
-        if tile == '.' {
-            bot = dest;
-            continue;
-        } else if tile == '#' {
-            continue;
+impl Warehouse {
+    fn new(map: Map<char>) -> Self {
+        Self {
+            bots: map.iter().filter(|pos| map[pos] == '@').collect(),
+            map,
+            log: Vec::with_capacity(512),
+            push_set: HashSet::with_capacity(512),
+            queue: VecDeque::with_capacity(10),
         }
+    }
+
+    /// Tries to move robot `bot`, pushing any boxes in the way. Returns whether it moved.
+    fn step(&mut self, bot: usize, dxy: Pos) -> bool {
+        let from = self.bots[bot];
+        let mut mv = Move { bot, dir: dxy, from, overwritten: Vec::new() };
 
-        push_set(dest, dxy, buf);
-        if buf.push_set.iter().all(|&pos| buf.map[pos + dxy] != '#') {
-            buf.changes.clear();
-            for &pos in &buf.push_set {
-                buf.changes.push((pos + dxy, buf.map[pos]));
-                buf.map[pos] = '.';
+        let free = self.push_set(from, dxy);
+        if free {
+            for &pos in &self.push_set {
+                mv.overwritten.push((pos, self.map[pos]));
+                if !self.push_set.contains(&(pos + dxy)) {
+                    mv.overwritten.push((pos + dxy, self.map[pos + dxy]));
+                }
             }
-            for &(new_pos, tile) in &buf.changes {
-                buf.map[new_pos] = tile;
+            for &pos in &self.push_set {
+                self.map[pos] = '.';
             }
-            bot = dest;
+            for &(pos, tile) in &mv.overwritten {
+                if self.push_set.contains(&pos) {
+                    self.map[pos + dxy] = tile;
+                }
+            }
+            self.bots[bot] = from + dxy;
         }
+
+        self.log.push(mv);
+        free
     }
-}
 
-fn find_bot(map: &Map<char>) -> Pos { map.iter().find(|pos| map[pos] == '@').unwrap() }
+    /// Takes back the last move, if there is one.
+    fn undo(&mut self) -> Option<Move> {
+        let mv = self.log.pop()?;
+        for &(pos, tile) in &mv.overwritten {
+            self.map[pos] = tile;
+        }
+        self.bots[mv.bot] = mv.from;
+        Some(mv)
+    }
 
-fn push_set(start_pos: Pos, dy: Pos, buf: &mut Buf) {
-    buf.push_set.clear();
-    buf.queue.clear();
+    /// Collects everything the robot at `start_pos` would push along `dy`, and
+    /// whether there's room for all of it to move.
+    fn push_set(&mut self, start_pos: Pos, dy: Pos) -> bool {
+        self.push_set.clear();
+        self.queue.clear();
 
-    buf.push_set.insert(start_pos);
-    buf.queue.push_back(start_pos);
+        self.push_set.insert(start_pos);
+        self.queue.push_back(start_pos + dy);
 
-    while let Some(pos) = buf.queue.pop_front() {
-        let tile = buf.map[pos];
-        if is_box(tile) {
-            buf.push_set.insert(pos);
-            buf.queue.push_back(pos + dy)
+        while let Some(pos) = self.queue.pop_front() {
+            if self.push_set.contains(&pos) {
+                continue;
+            }
+            match self.map[pos] {
+                '.' => {}
+                '#' | '@' => return false,
+                _ => {
+                    for part in self.box_at(pos) {
+                        self.push_set.insert(part);
+                        self.queue.push_back(part + dy);
+                    }
+                }
+            }
         }
-        if tile == ']' && !buf.push_set.contains(&(pos + WEST)) {
-            buf.queue.push_back(pos + WEST);
+        true
+    }
+
+    /// All the tiles of the box covering `pos`.
+    fn box_at(&self, pos: Pos) -> impl Iterator<Item = Pos> {
+        let mut left = pos;
+        while !matches!(self.map[left], 'O' | '[') {
+            left += WEST;
         }
-        if tile == '[' && !buf.push_set.contains(&(pos + EAST)) {
-            buf.queue.push_back(pos + EAST);
+        let mut right = pos;
+        while !matches!(self.map[right], 'O' | ']') {
+            right += EAST;
         }
+        (left.x..=right.x).map(move |x| Pos::new(x, pos.y))
     }
-}
 
-fn tally(map: &Map<char>, box_ch: char) -> i32 {
-    map.iter().filter(|pos| map[pos] == box_ch).map(|pos| pos.y * 100 + pos.x).sum()
-}
+    fn render(&self) -> String {
+        (0..self.map.size.y)
+            .map(|y| {
+                (0..self.map.size.x)
+                    .map(|x| Pos::new(x, y))
+                    .map(|pos| self.map[pos])
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 
-fn is_box(tile: char) -> bool { tile == 'O' || tile == '[' || tile == ']' }
+    /// Sum of the GPS coordinates of the boxes, measured from their left edge.
+    fn tally(&self) -> i32 {
+        self.map
+            .iter()
+            .filter(|pos| matches!(self.map[pos], 'O' | '['))
+            .map(|pos| pos.y * 100 + pos.x)
+            .sum()
+    }
+}
 
 aoc_2024::main! {
     solve()
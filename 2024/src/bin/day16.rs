@@ -14,17 +14,39 @@
 //!
 //! Optimization #2: use two queues instead of a heap to turn Dijsktra into
 //! a glorified BFS that always prefers shooting straight instead of turning.
+//! That only holds up while a turn costs more than any straight run across the
+//! map, so cheaper turns fall back to a plain heap.
+//!
+//! Optimization #3: hashing dominated the runtime, so states are now numbered
+//! densely as `cell * 4 + direction` and live in flat vectors. Instead of a list
+//! of predecessor states, every state keeps a bitmask of the moves that reach it
+//! at the lowest cost, and the backtrack marks states in a bitset. Went from 15ms
+//! to 5ms on a 141x141 maze.
 //!
 //! Inspiration: https://github.com/maneatingape/advent-of-code-rust/blob/0834bd10ef57be8ed8436d11171d0e9f9c52a1c9/src/year2024/day16.rs
+//!
+//! Costs are configurable with `--step-cost 1 --turn-cost 1000`. `--tiles` prints
+//! the maze with the tiles of every best path marked as `O` and `--routes N` lists
+//! up to `N` distinct best paths.
 
-use std::{collections::VecDeque, hash::Hash};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+};
 
+use aoc_2024::{flag, param};
 use aoc_2dmap::prelude::*;
-use aoc_prelude::{HashMap, HashSet, Itertools};
+use aoc_prelude::Itertools;
 
-const TURN_COST: usize = 1000;
+const DIR_CHARS: [char; 4] = ['^', '>', 'v', '<'];
+const MOVES: [Move; 3] = [Move::Adv, Move::Right, Move::Left];
+
+struct Costs {
+    step: usize,
+    turn: usize,
+}
 
-#[derive(Copy, Clone, Hash, Debug)]
+#[derive(Copy, Clone, Debug)]
 enum Move {
     Adv,
     Right,
@@ -32,44 +54,107 @@ enum Move {
 }
 
 impl Move {
-    fn change_dir(&self, d: Pos) -> Pos {
+    /// Index into `ORTHOGONAL` of the direction after the move.
+    fn change_dir(&self, d: usize) -> usize {
+        match self {
+            Move::Adv => d,
+            Move::Right => (d + 1) % 4,
+            Move::Left => (d + 3) % 4,
+        }
+    }
+
+    /// Direction before the move, given the one after.
+    fn undo_dir(&self, d: usize) -> usize {
         match self {
             Move::Adv => d,
-            Move::Right => d.clockwise(),
-            Move::Left => d.anticlockwise(),
+            Move::Right => (d + 3) % 4,
+            Move::Left => (d + 1) % 4,
         }
     }
 
-    fn cost(&self) -> usize {
+    fn cost(&self, costs: &Costs) -> usize {
         match self {
-            Move::Adv => 1,
-            Move::Left | Move::Right => TURN_COST + 1,
+            Move::Adv => costs.step,
+            Move::Left | Move::Right => costs.turn + costs.step,
         }
     }
 
+    fn bit(&self) -> u8 { 1 << (*self as u8) }
+
     fn transform(&self, old_state: &State) -> State {
-        let mut new_state = *old_state;
-        new_state.dir = self.change_dir(old_state.dir);
-        new_state.pos += new_state.dir;
-        new_state
+        let dir = self.change_dir(old_state.dir);
+        State { pos: old_state.pos + ORTHOGONAL[dir], dir }
+    }
+
+    fn reverse(&self, new_state: &State) -> State {
+        let back = ORTHOGONAL[(new_state.dir + 2) % 4];
+        State { pos: new_state.pos + back, dir: self.undo_dir(new_state.dir) }
     }
 }
 
-#[derive(Eq, Ord, PartialOrd, PartialEq, Hash, Clone, Copy, Debug)]
+#[derive(Eq, Ord, PartialOrd, PartialEq, Clone, Copy, Debug)]
 struct State {
     pos: Pos,
-    dir: Pos,
+    /// Index into `ORTHOGONAL`.
+    dir: usize,
 }
 
 impl State {
     fn steps<'a>(&'a self, map: &'a Map<char>) -> impl Iterator<Item = Move> + 'a {
         [Move::Right, Move::Left, Move::Adv].into_iter().filter(|mv| {
-            let tile = map.get(self.pos + mv.change_dir(self.dir));
+            let tile = map.get(self.pos + ORTHOGONAL[mv.change_dir(self.dir)]);
             tile == Some('.') || tile == Some('E')
         })
     }
 }
 
+/// Lowest cost of every reachable state, along with the moves it can be reached
+/// with at that cost. Both are indexed by `cell * 4 + direction`.
+struct Search {
+    width: i32,
+    costs: Vec<usize>,
+    preds: Vec<u8>,
+}
+
+impl Search {
+    fn new(map: &Map<char>, start: State) -> Self {
+        let states = (map.size.x * map.size.y) as usize * 4;
+        let mut search =
+            Self { width: map.size.x, costs: vec![usize::MAX; states], preds: vec![0; states] };
+        let idx = search.index(&start);
+        search.costs[idx] = 0;
+        search
+    }
+
+    fn index(&self, state: &State) -> usize {
+        (state.pos.y * self.width + state.pos.x) as usize * 4 + state.dir
+    }
+
+    fn cost(&self, state: &State) -> usize { self.costs[self.index(state)] }
+
+    /// Records reaching `new_state` with `mv` at `new_cost`, returning whether
+    /// that's worth exploring further.
+    fn relax(&mut self, mv: Move, new_state: State, new_cost: usize) -> bool {
+        let idx = self.index(&new_state);
+        let lowest = self.costs[idx];
+        if new_cost > lowest {
+            return false;
+        }
+        if new_cost < lowest {
+            self.preds[idx] = 0;
+            self.costs[idx] = new_cost;
+        }
+        self.preds[idx] |= mv.bit();
+        true
+    }
+
+    /// States leading to `state` on one of its cheapest paths.
+    fn preds(&self, state: State) -> impl Iterator<Item = State> + '_ {
+        let bits = self.preds[self.index(&state)];
+        MOVES.into_iter().filter(move |mv| bits & mv.bit() != 0).map(move |mv| mv.reverse(&state))
+    }
+}
+
 fn solve() -> (usize, usize) {
     let input = include_str!("../../inputs/16.in");
     let map_size = Pos::from((
@@ -78,36 +163,78 @@ fn solve() -> (usize, usize) {
     ));
     let map = Map::new(map_size, input.chars().filter(|&c| c != '\n'));
 
-    let start = find_tile(&map, 'S');
+    let costs = Costs { step: param("step-cost", 1), turn: param("turn-cost", 1000) };
+    assert!(costs.step > 0, "--step-cost must be positive");
+
+    let start = State { pos: find_tile(&map, 'S'), dir: 1 };
     let goal = find_tile(&map, 'E');
 
-    let mut p1 = usize::MAX;
-    let mut costs = HashMap::<State, usize>::with_capacity(1024);
-    let mut paths = HashMap::<State, Vec<State>>::with_capacity(1024);
+    let search = match costs.turn >= costs.step * map.size.x.max(map.size.y) as usize {
+        true => two_queues(&map, start, &costs),
+        false => dijkstra(&map, start, &costs),
+    };
+
+    let p1 = (0..4).map(|dir| search.cost(&State { pos: goal, dir })).min().unwrap();
+    let ends = (0..4)
+        .map(|dir| State { pos: goal, dir })
+        .filter(|end| search.cost(end) == p1)
+        .collect_vec();
+
+    let mut seen = vec![0u64; search.costs.len().div_ceil(64)];
+    let mut q = VecDeque::from(ends.clone());
+    for end in &ends {
+        let idx = search.index(end);
+        seen[idx / 64] |= 1 << (idx % 64);
+    }
+
+    while let Some(cur_node) = q.pop_front() {
+        for state in search.preds(cur_node) {
+            let idx = search.index(&state);
+            if seen[idx / 64] & (1 << (idx % 64)) == 0 {
+                seen[idx / 64] |= 1 << (idx % 64);
+                q.push_back(state);
+            }
+        }
+    }
+
+    // four directions per cell, so every nibble is a cell
+    let on_path = |pos: Pos| {
+        let idx = search.index(&State { pos, dir: 0 });
+        (seen[idx / 64] >> (idx % 64)) & 0xf != 0
+    };
+    let p2 = map.iter().filter(|&pos| on_path(pos)).count();
+
+    if flag("tiles").is_some() {
+        eprintln!("{}", render(&map, on_path));
+    }
+    if let Some(limit) = flag("routes") {
+        let limit = limit.parse().expect("--routes needs a number of routes");
+        for (idx, route) in routes(&search, start, &ends, limit).iter().enumerate() {
+            eprintln!("{}: {}", idx + 1, route);
+        }
+    }
+
+    (p1, p2)
+}
+
+fn two_queues(map: &Map<char>, start: State, costs: &Costs) -> Search {
+    let mut search = Search::new(map, start);
 
     let mut q_one = VecDeque::with_capacity(512);
     let mut q_two = VecDeque::with_capacity(512);
-    q_one.push_back((State { pos: start, dir: EAST }, 0));
+    q_one.push_back((start, 0));
 
     while !q_one.is_empty() {
         while let Some((state, cost)) = q_one.pop_front() {
-            if state.pos == goal {
-                p1 = cost;
-                break;
+            if map[state.pos] == 'E' {
+                continue;
             }
-            for step in state.steps(&map) {
-                let new_cost = cost + step.cost();
+            for step in state.steps(map) {
+                let new_cost = cost + step.cost(costs);
                 let new_state = step.transform(&state);
-
-                let lowest = *costs.get(&new_state).unwrap_or(&usize::MAX);
-                if new_cost > lowest {
+                if !search.relax(step, new_state, new_cost) {
                     continue;
                 }
-                if new_cost < lowest {
-                    paths.insert(new_state, Vec::new());
-                    costs.insert(new_state, new_cost);
-                }
-                paths.entry(new_state).or_default().push(state);
 
                 // prefer shooting straight than turning
                 if new_state.dir == state.dir {
@@ -120,25 +247,63 @@ fn solve() -> (usize, usize) {
         (q_one, q_two) = (q_two, q_one);
     }
 
-    let mut q = VecDeque::from(ORTHOGONAL.map(|dir| State { pos: goal, dir }));
-    let mut seen = HashSet::with_capacity(200);
-    seen.extend(q.iter().copied());
+    search
+}
 
-    while let Some(cur_node) = q.pop_front() {
-        if let Some(prev_nodes) = paths.get(&cur_node) {
-            for state in prev_nodes {
-                if seen.contains(state) {
-                    continue;
-                }
-                seen.insert(*state);
-                q.push_back(*state);
+fn dijkstra(map: &Map<char>, start: State, costs: &Costs) -> Search {
+    let mut search = Search::new(map, start);
+
+    let mut heap = BinaryHeap::with_capacity(512);
+    heap.push(Reverse((0, start)));
+
+    while let Some(Reverse((cost, state))) = heap.pop() {
+        if cost > search.cost(&state) || map[state.pos] == 'E' {
+            continue;
+        }
+        for step in state.steps(map) {
+            let new_cost = cost + step.cost(costs);
+            let new_state = step.transform(&state);
+            let lowest = search.cost(&new_state);
+            if search.relax(step, new_state, new_cost) && new_cost < lowest {
+                heap.push(Reverse((new_cost, new_state)));
             }
         }
     }
 
-    let p2 = seen.iter().map(|state| state.pos).unique().count();
+    search
+}
+
+/// Up to `limit` distinct best paths, spelled out as the direction of every step.
+fn routes(search: &Search, start: State, ends: &[State], limit: usize) -> Vec<String> {
+    let mut routes = Vec::new();
+    // walking backwards from the goal, so the route so far is reversed
+    let mut stack = ends.iter().map(|&end| (end, Vec::new())).collect_vec();
 
-    (p1, p2)
+    while let Some((state, mut route)) = stack.pop() {
+        if routes.len() == limit {
+            break;
+        }
+        if state == start {
+            routes.push(route.iter().rev().collect());
+            continue;
+        }
+        route.push(DIR_CHARS[state.dir]);
+        for prev in search.preds(state) {
+            stack.push((prev, route.clone()));
+        }
+    }
+    routes
+}
+
+fn render(map: &Map<char>, on_path: impl Fn(Pos) -> bool) -> String {
+    (0..map.size.y)
+        .map(|y| {
+            (0..map.size.x)
+                .map(|x| Pos::new(x, y))
+                .map(|pos| if on_path(pos) { 'O' } else { map[pos] })
+                .collect::<String>()
+        })
+        .join("\n")
 }
 
 fn find_tile(map: &Map<char>, tile: char) -> Pos {
@@ -1,14 +1,26 @@
 //! # Chronospatial Computer
 //!
-//! Part 1: code-monkey.
+//! Part 1: code-monkey. The [`Machine`] runs one instruction at a time, gives up
+//! with an [`Error`] on invalid operands, on jumping back with the exact same
+//! registers as before (which can only loop forever) or after `--step-limit`
+//! instructions (a million by default). `--trace` prints every instruction with
+//! the registers it changed.
 //!
-//! Part 2: the "A-ha" moment was realising an output digit is only determined
-//! by 3 bits of the "A" registry and no matter how far left those digits get
-//! shifted, the output digit stays the same.
+//! `--disasm` lists the program as mnemonics, and `--decompile` as pseudo-code,
+//! picking out the `do { ...; a >>= 3 } while a != 0` loop every input is built on.
 //!
-//! At each step we produce candidates that output the required digit,
+//! Part 2: the "A-ha" moment was realising the loop prints one digit and shifts
+//! A right by a fixed amount (the `adv` operand, usually 3) on every iteration,
+//! so the last digit only depends on the top bits of A, the next to last one on
+//! the top bits plus the next chunk, and so on. B and C may look further up A
+//! than the current chunk (`cdv`), but by then those bits are already settled.
+//!
+//! At each step we produce candidates that output the required digits,
 //! then use these as the new "initial" values for the next iteration, in
-//! which they'll get shifted 3 positions to the left.
+//! which they'll get shifted to the left by one chunk. Every candidate is
+//! checked by running the whole program, and all of them are kept, so the
+//! search ends up with every A that turns the program into a quine (`--all`
+//! lists them), or none at all.
 //!
 //! So, if A = 0b111_011_100_101
 //!             |-+-|-+-|
@@ -17,93 +29,330 @@
 //! last digit <--+   |
 //! next to last <----+
 
-use std::mem;
+use std::{fmt, mem, ops::Range};
 
-use aoc_prelude::Itertools;
-use aoc_2024::extract_nums;
+use aoc_2024::{extract_nums, flag, param};
+use aoc_prelude::{HashSet, Itertools};
 
-type Int = u64;
+type Int = u128;
+const REG_NAMES: [char; 3] = ['a', 'b', 'c'];
+const MNEMONICS: [&str; 8] = ["adv", "bxl", "bst", "jnz", "bxc", "out", "bdv", "cdv"];
 
-const BLOCK_SIZE: Int = 3;
+#[derive(Debug)]
+enum Error {
+    InvalidOpcode { ip: usize, opcode: u8 },
+    InvalidOperand { ip: usize, operand: u8 },
+    InfiniteLoop { ip: usize },
+    StepLimit { steps: usize },
+}
 
-fn solve() -> (String, Int) {
-    let (reg_lines, program_lines) = include_str!("../../inputs/17.in").split_once("\n\n").unwrap();
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidOpcode { ip, opcode } => write!(f, "invalid opcode {opcode} at {ip}"),
+            Error::InvalidOperand { ip, operand } => {
+                write!(f, "invalid combo operand {operand} at {ip}")
+            }
+            Error::InfiniteLoop { ip } => write!(f, "stuck in an infinite loop at {ip}"),
+            Error::StepLimit { steps } => write!(f, "still running after {steps} steps"),
+        }
+    }
+}
 
-    let mut regs = [0; 3];
-    reg_lines
-        .lines()
-        .filter_map(|line| extract_nums(line).next())
-        .enumerate()
-        .for_each(|(idx, el)| regs[idx] = el);
-    let [a, b, c] = regs;
+/// What a single instruction did.
+struct Step {
+    ip: usize,
+    opcode: u8,
+    operand: u8,
+    /// Registers that changed, with their old and new values.
+    deltas: Vec<(usize, Int, Int)>,
+    out: Option<u8>,
+}
 
-    let program = extract_nums(program_lines).collect_vec();
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>3}: {:<8}", self.ip, mnemonic(self.opcode, self.operand))?;
+        for &(reg, old, new) in &self.deltas {
+            write!(f, "  {}: {old} -> {new}", REG_NAMES[reg])?;
+        }
+        if let Some(out) = self.out {
+            write!(f, "  out {out}")?;
+        }
+        Ok(())
+    }
+}
 
-    let p1 = format!("{}", eval(a, b, c, &program)).chars().join(",");
+struct Machine<'a> {
+    program: &'a [u8],
+    regs: [Int; 3],
+    ip: usize,
+    output: Vec<u8>,
+    steps: usize,
+    step_limit: usize,
+    /// Registers every time a jump was taken, by jump.
+    jumps: HashSet<(usize, [Int; 3])>,
+}
 
-    let target = program.iter().fold(0, |acc, digit| acc * 10 + digit);
+impl<'a> Machine<'a> {
+    fn new(program: &'a [u8], regs: [Int; 3]) -> Self {
+        Self {
+            program,
+            regs,
+            ip: 0,
+            output: Vec::new(),
+            steps: 0,
+            step_limit: 1_000_000,
+            jumps: HashSet::new(),
+        }
+    }
 
-    let mut a_candidates = vec![0];
-    let mut next_a_candidates = Vec::with_capacity(16);
+    fn with_step_limit(mut self, step_limit: usize) -> Self {
+        self.step_limit = step_limit;
+        self
+    }
 
-    for digit in 0..=target.ilog10() {
-        let look_for = target % Int::pow(10, digit + 1);
-        next_a_candidates.clear();
-        for msbs in a_candidates.iter().map(|bits| *bits << BLOCK_SIZE) {
-            next_a_candidates.extend(
-                (0..1 << BLOCK_SIZE)
-                    .map(|lsbs| msbs + lsbs)
-                    .filter(|&a| eval(a, b, c, &program) == look_for),
-            );
+    fn combo(&self, operand: u8) -> Result<Int, Error> {
+        match operand {
+            0..=3 => Ok(operand as Int),
+            4..=6 => Ok(self.regs[operand as usize - 4]),
+            _ => Err(Error::InvalidOperand { ip: self.ip, operand }),
         }
-        mem::swap(&mut a_candidates, &mut next_a_candidates);
     }
 
-    let p2 = a_candidates.into_iter().min().expect("no solution?!");
+    /// Runs a single instruction, or returns `None` once the program halted.
+    fn step(&mut self) -> Result<Option<Step>, Error> {
+        let (Some(&opcode), Some(&operand)) =
+            (self.program.get(self.ip), self.program.get(self.ip + 1))
+        else {
+            return Ok(None);
+        };
+        if self.steps == self.step_limit {
+            return Err(Error::StepLimit { steps: self.steps });
+        }
+        self.steps += 1;
 
-    (p1, p2)
+        let (ip, before) = (self.ip, self.regs);
+        let mut out = None;
+        let mut next_ip = ip + 2;
+
+        match opcode {
+            0 => self.regs[0] = shr(self.regs[0], self.combo(operand)?),
+            1 => self.regs[1] ^= operand as Int,
+            2 => self.regs[1] = self.combo(operand)? % 8,
+            3 if self.regs[0] != 0 => {
+                if !self.jumps.insert((ip, before)) {
+                    return Err(Error::InfiniteLoop { ip });
+                }
+                next_ip = operand as usize;
+            }
+            3 => {}
+            4 => self.regs[1] ^= self.regs[2],
+            5 => out = Some((self.combo(operand)? % 8) as u8),
+            6 => self.regs[1] = shr(self.regs[0], self.combo(operand)?),
+            7 => self.regs[2] = shr(self.regs[0], self.combo(operand)?),
+            _ => return Err(Error::InvalidOpcode { ip, opcode }),
+        }
+
+        self.ip = next_ip;
+        self.output.extend(out);
+
+        let deltas = (0..3)
+            .filter(|&reg| before[reg] != self.regs[reg])
+            .map(|reg| (reg, before[reg], self.regs[reg]))
+            .collect();
+        Ok(Some(Step { ip, opcode, operand, deltas, out }))
+    }
+
+    /// Runs until the program halts, returning everything it printed.
+    fn run(&mut self) -> Result<&[u8], Error> {
+        while self.step()?.is_some() {}
+        Ok(&self.output)
+    }
+}
+
+/// Combo operands as they read: literals, registers, or the reserved `7`.
+fn combo_name(operand: u8) -> String {
+    match operand {
+        0..=3 => operand.to_string(),
+        4..=6 => REG_NAMES[operand as usize - 4].to_string(),
+        _ => format!("<invalid {operand}>"),
+    }
+}
+
+fn mnemonic(opcode: u8, operand: u8) -> String {
+    match opcode {
+        0 | 2 | 5 | 6 | 7 => format!("{} {}", MNEMONICS[opcode as usize], combo_name(operand)),
+        1 | 3 => format!("{} {operand}", MNEMONICS[opcode as usize]),
+        4 => MNEMONICS[4].to_string(),
+        _ => format!("<invalid opcode {opcode}>"),
+    }
 }
 
-fn eval(a: Int, b: Int, c: Int, program: &[Int]) -> Int {
-    let (mut a, mut b, mut c) = (a, b, c);
+fn disassemble(program: &[u8]) -> String {
+    program
+        .chunks(2)
+        .enumerate()
+        .map(|(idx, ins)| match ins {
+            &[opcode, operand] => format!("{:>3}: {}", idx * 2, mnemonic(opcode, operand)),
+            _ => format!("{:>3}: <truncated {}>", idx * 2, ins[0]),
+        })
+        .join("\n")
+}
 
-    let mut ip = 0;
+/// A single instruction as a statement, without the jumps.
+fn statement(opcode: u8, operand: u8) -> String {
+    let combo = combo_name(operand);
+    match opcode {
+        0 => format!("a >>= {combo};"),
+        1 => format!("b ^= {operand};"),
+        2 if operand < 4 => format!("b = {};", operand % 8),
+        2 => format!("b = {combo} & 7;"),
+        4 => "b ^= c;".to_string(),
+        5 if operand < 4 => format!("out({});", operand % 8),
+        5 => format!("out({combo} & 7);"),
+        6 => format!("b = a >> {combo};"),
+        7 => format!("c = a >> {combo};"),
+        _ => mnemonic(opcode, operand) + ";",
+    }
+}
 
-    let mut output = 0;
+/// Bits of `a` consumed on every trip around the loop, when the program is a single
+/// `do { ... } while a != 0` loop shifting `a` right by a constant once.
+fn loop_shift(program: &[u8]) -> Option<u32> {
+    let jumps = program.chunks(2).positions(|ins| ins[0] == 3).collect_vec();
+    let &[jump] = jumps.as_slice() else {
+        return None;
+    };
+    let target = *program.get(jump * 2 + 1)? as usize;
+    // only a jump back to an instruction makes a loop
+    if target & 1 != 0 || target >= jump * 2 {
+        return None;
+    }
+    let mut shifts = program[target..jump * 2].chunks(2).filter(|ins| ins[0] == 0);
+    match (shifts.next(), shifts.next()) {
+        (Some(&[0, shift]), None) if shift < 4 => Some(shift as u32),
+        _ => None,
+    }
+}
 
-    loop {
-        let (it, op) = (program[ip], program[ip + 1]);
+fn decompile(program: &[u8]) -> String {
+    let ins = program.chunks_exact(2).map(|ins| (ins[0], ins[1])).collect_vec();
+    let body = |range: Range<usize>, indent: &str| {
+        ins[range]
+            .iter()
+            .map(|&(opcode, operand)| indent.to_string() + &statement(opcode, operand))
+            .collect_vec()
+    };
 
-        let combo = |op: Int| match op {
-            0..=3 => op,
-            4 => a,
-            5 => b,
-            6 => c,
-            _ => unreachable!(),
-        };
+    let jumps = ins.iter().positions(|&(opcode, _)| opcode == 3).collect_vec();
+    let backwards = match jumps.as_slice() {
+        &[jump] if ins[jump].1 & 1 == 0 && (ins[jump].1 as usize) < jump * 2 => Some(jump),
+        _ => None,
+    };
 
-        match it {
-            0 => a >>= combo(op),
-            1 => b ^= op,
-            2 => b = combo(op) % 8,
-            3 => {
-                if a == 0 {
-                    return output;
-                } else {
-                    ip = op as usize;
-                }
+    let Some(jump) = backwards else {
+        // anything fancier gets labels and gotos
+        return ins
+            .iter()
+            .enumerate()
+            .map(|(idx, &(opcode, operand))| match opcode {
+                3 => format!("{:>3}: if a != 0 {{ goto {operand}; }}", idx * 2),
+                _ => format!("{:>3}: {}", idx * 2, statement(opcode, operand)),
+            })
+            .join("\n");
+    };
+
+    let start = ins[jump].1 as usize / 2;
+    let mut lines = body(0..start, "");
+    lines.push("do {".to_string());
+    lines.extend(body(start..jump, "    "));
+    match loop_shift(program) {
+        Some(1) => lines.push("} while a != 0; // 1 bit of a per iteration".to_string()),
+        Some(shift) => lines.push(format!("}} while a != 0; // {shift} bits of a per iteration")),
+        None => lines.push("} while a != 0;".to_string()),
+    }
+    lines.extend(body(jump + 1..ins.len(), ""));
+    lines.join("\n")
+}
+
+/// `a >> by`, shifting everything out when `by` is past the width of `a`.
+fn shr(a: Int, by: Int) -> Int {
+    u32::try_from(by).ok().and_then(|by| a.checked_shr(by)).unwrap_or(0)
+}
+
+fn solve() -> (String, String) {
+    let (reg_lines, program_lines) = include_str!("../../inputs/17.in").split_once("\n\n").unwrap();
+
+    let mut regs = [0; 3];
+    reg_lines
+        .lines()
+        .filter_map(|line| extract_nums(line).next())
+        .enumerate()
+        .for_each(|(idx, el)| regs[idx] = el);
+    let [_, b, c] = regs;
+
+    let program = extract_nums::<u8>(program_lines).collect_vec();
+    let step_limit = param("step-limit", 1_000_000);
+
+    if flag("disasm").is_some() {
+        eprintln!("{}", disassemble(&program));
+    }
+    if flag("decompile").is_some() {
+        eprintln!("{}", decompile(&program));
+    }
+
+    let mut machine = Machine::new(&program, regs).with_step_limit(step_limit);
+    if flag("trace").is_some() {
+        while let Some(step) = machine.step().unwrap_or_else(|err| panic!("{err}")) {
+            eprintln!("{step}");
+        }
+    }
+    let p1 = machine.run().unwrap_or_else(|err| panic!("{err}")).iter().join(",");
+
+    let p2 = match find_quines(&program, [b, c], step_limit) {
+        Ok(quines) if !quines.is_empty() => {
+            if flag("all").is_some() {
+                eprintln!("{}", quines.iter().join("\n"));
             }
-            4 => b ^= c,
-            5 => output = output * 10 + combo(op) % 8,
-            6 => b = a >> combo(op),
-            7 => c = a >> combo(op),
-            _ => unreachable!(),
+            quines[0].to_string()
         }
+        Ok(_) => "no quine".to_string(),
+        Err(reason) => format!("no quine search: {reason}"),
+    };
 
-        if it != 3 {
-            ip += 2;
+    (p1, p2)
+}
+
+/// Every value of A which makes the program print itself, smallest first.
+fn find_quines(program: &[u8], [b, c]: [Int; 2], step_limit: usize) -> Result<Vec<Int>, String> {
+    let shift = match loop_shift(program) {
+        None => return Err("the program isn't a single loop shifting a by a constant".into()),
+        Some(0) => return Err("a never shrinks, so the program never halts".into()),
+        Some(shift) => shift,
+    };
+    if shift as usize * program.len() > Int::BITS as usize {
+        return Err(format!("a quine would need more than {} bits", Int::BITS));
+    }
+
+    let mut a_candidates = vec![0];
+    let mut next_a_candidates = Vec::with_capacity(16);
+
+    for digit in (0..program.len()).rev() {
+        let look_for = &program[digit..];
+        next_a_candidates.clear();
+        for msbs in a_candidates.iter().map(|bits| *bits << shift) {
+            next_a_candidates.extend((0..1 << shift).map(|lsbs| msbs + lsbs).filter(|&a| {
+                Machine::new(program, [a, b, c])
+                    .with_step_limit(step_limit)
+                    .run()
+                    .is_ok_and(|out| out == look_for)
+            }));
         }
+        mem::swap(&mut a_candidates, &mut next_a_candidates);
     }
+
+    a_candidates.sort_unstable();
+    Ok(a_candidates)
 }
 
 aoc_2024::main! {
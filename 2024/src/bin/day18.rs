@@ -11,22 +11,66 @@
 //! shortest path, and _only then_ re-perform the Dijsktra. This got the number
 //! of Dijsktra runs down to ~35.
 //!
-//! Optimization #2: replace Dijsktra with DFS since all edges have equal cost.
+//! Optimization #2: replace Dijsktra with BFS since all edges have equal cost.
+//!
+//! Optimization #3: no searches at all for part 2. Place every byte, union all
+//! the free cells that touch, then take the bytes back out starting from the
+//! last one, merging each freed cell with its free neighbours. The first byte
+//! to block the way is the one whose removal finally joins start and goal.
+//!
+//! `--timeline` lists every byte with whether there's still a way out after it
+//! fell, and how long the shortest one is.
+//!
+//! The memory space is 71x71 with 1024 bytes fallen for part 1, from the top left
+//! to the bottom right corner, unless told otherwise with `--size 7 --bytes 12`,
+//! `--start 0,0` and `--goal 6,6`.
 
-use std::collections::VecDeque;
+use std::{collections::VecDeque, fmt};
 
+use aoc_2024::{extract_nums, flag, param};
 use aoc_2dmap::prelude::{Map, Pos, ORTHOGONAL};
 use aoc_prelude::{HashMap, HashSet, Itertools};
-use aoc_2024::extract_nums;
 
-const MAP_SIZE: i32 = 71;
-const INIT_BLOCKS: usize = 1024;
-const START: Pos = Pos::c_new(0, 0);
-const GOAL: Pos = Pos::c_new(MAP_SIZE - 1, MAP_SIZE - 1);
+/// A square memory space and where to cross it.
+struct Memory {
+    size: i32,
+    start: Pos,
+    goal: Pos,
+}
+
+impl Memory {
+    fn map(&self) -> Map<char> { Map::fill((self.size, self.size), '.') }
+
+    fn index_of(&self, p: Pos) -> usize { (p.y * self.size + p.x) as usize }
+}
+
+/// Length of the shortest way out, if there's still one.
+struct Steps(Option<usize>);
+
+impl fmt::Display for Steps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(steps) => write!(f, "{steps}"),
+            None => write!(f, "the goal is cut off"),
+        }
+    }
+}
+
+/// The first byte to block the way, printed as the puzzle wants it: `x,y`.
+struct Coords(Option<Pos>);
+
+impl fmt::Display for Coords {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(pos) => write!(f, "{},{}", pos.x, pos.y),
+            None => write!(f, "no byte blocks the path"),
+        }
+    }
+}
 
 struct Buf {
     costs: HashMap<Pos, usize>,
-    queue: VecDeque<(usize, Pos)>,
+    queue: VecDeque<Pos>,
     edges: HashMap<Pos, Pos>,
     path: HashSet<Pos>,
 }
@@ -48,8 +92,39 @@ impl Buf {
     }
 }
 
-fn solve() -> (usize, String) {
-    let mut map = Map::<char>::fill((MAP_SIZE, MAP_SIZE), '.');
+/// Union-find over the cells of the map.
+struct Dsu {
+    parent: Vec<usize>,
+}
+
+impl Dsu {
+    fn new(size: usize) -> Self { Self { parent: (0..size).collect() } }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a] = b;
+    }
+}
+
+fn solve() -> (Steps, Coords) {
+    let size = param("size", 71);
+    let mem = Memory {
+        size,
+        start: flag("start").map_or(Pos::new(0, 0), |s| parse_pos(&s)),
+        goal: flag("goal").map_or(Pos::new(size - 1, size - 1), |s| parse_pos(&s)),
+    };
+    assert!(
+        mem.map().within(mem.start) && mem.map().within(mem.goal),
+        "start and goal must be in memory"
+    );
 
     let blocks = include_str!("../../inputs/18.in")
         .lines()
@@ -59,73 +134,127 @@ fn solve() -> (usize, String) {
         })
         .collect_vec();
 
-    for block in blocks.iter().take(INIT_BLOCKS) {
+    let mut map = mem.map();
+    for block in blocks.iter().take(param("bytes", 1024)) {
         map[block] = '#';
     }
 
     let mut buf = Buf::default();
 
-    let p1 = dfs(&map, &mut buf).expect("no path!?");
+    let p1 = Steps(bfs(&map, &mem, &mut buf));
 
-    let mut choke = None;
-    for block in blocks.iter().skip(INIT_BLOCKS) {
-        map[block] = '#';
-        if buf.path.contains(block) && dfs(&map, &mut buf).is_none() {
-            choke = Some(block);
-            break;
+    let choke = first_blocking(&blocks, &mem);
+
+    if flag("timeline").is_some() {
+        let mut map = mem.map();
+        let mut shortest = bfs(&map, &mem, &mut buf);
+        for (idx, block) in blocks.iter().enumerate() {
+            map[block] = '#';
+            if shortest.is_some() && buf.path.contains(block) {
+                shortest = bfs(&map, &mem, &mut buf);
+            }
+            let connected = !matches!(choke, Some(choke) if idx >= choke);
+            eprintln!(
+                "{:>5}: {},{} {}",
+                idx + 1,
+                block.x,
+                block.y,
+                shortest
+                    .filter(|_| connected)
+                    .map_or("blocked".into(), |len| format!("{len} steps"))
+            );
         }
     }
 
-    let p2 = choke.map(|pos| format!("{},{}", pos.x, pos.y)).expect("no block!?");
+    let p2 = Coords(choke.map(|idx| blocks[idx]));
 
     (p1, p2)
 }
 
-fn dfs(map: &Map<char>, buf: &mut Buf) -> Option<usize> {
-    buf.clear();
-    buf.queue.push_back((0, START));
+fn parse_pos(s: &str) -> Pos {
+    let (x, y) = extract_nums(s).collect_tuple().expect("expected x,y");
+    Pos::new(x, y)
+}
 
-    let mut p1 = usize::MAX;
+/// Index of the first byte that cuts the start off from the goal.
+fn first_blocking(blocks: &[Pos], mem: &Memory) -> Option<usize> {
+    let index_of = |p: Pos| mem.index_of(p);
 
-    while let Some((cost, cur)) = buf.queue.pop_back() {
-        if cur == GOAL {
-            p1 = cost;
-            break;
-        }
-        for step in ORTHOGONAL {
-            let next = cur + step;
-            if map.get(next) != Some('.') {
-                continue;
+    // a byte may fall on the same cell more than once
+    let mut fallen = vec![0; (mem.size * mem.size) as usize];
+    for block in blocks {
+        fallen[index_of(*block)] += 1;
+    }
+
+    let map = mem.map();
+    let mut dsu = Dsu::new(fallen.len());
+    let free_up = |dsu: &mut Dsu, fallen: &[usize], pos: Pos| {
+        for next in ORTHOGONAL.map(|step| pos + step) {
+            if map.within(next) && fallen[index_of(next)] == 0 {
+                dsu.union(index_of(pos), index_of(next));
             }
+        }
+    };
 
-            let cost = cost + 1;
-            let lowest = *buf.costs.get(&next).unwrap_or(&usize::MAX);
+    for pos in map.iter().filter(|&pos| fallen[index_of(pos)] == 0) {
+        free_up(&mut dsu, &fallen, pos);
+    }
+    let connected = |dsu: &mut Dsu, fallen: &[usize]| {
+        fallen[index_of(mem.start)] == 0
+            && fallen[index_of(mem.goal)] == 0
+            && dsu.find(index_of(mem.start)) == dsu.find(index_of(mem.goal))
+    };
+    if connected(&mut dsu, &fallen) {
+        return None;
+    }
 
-            if cost < lowest {
-                buf.edges.insert(next, cur);
-                buf.costs.insert(next, cost);
-                buf.queue.push_back((cost, next));
+    for (idx, &block) in blocks.iter().enumerate().rev() {
+        fallen[index_of(block)] -= 1;
+        if fallen[index_of(block)] == 0 {
+            free_up(&mut dsu, &fallen, block);
+            if connected(&mut dsu, &fallen) {
+                return Some(idx);
             }
         }
     }
+    unreachable!("the start or goal is blocked before any byte fell")
+}
 
-    if p1 == usize::MAX {
+fn bfs(map: &Map<char>, mem: &Memory, buf: &mut Buf) -> Option<usize> {
+    buf.clear();
+    if map[mem.start] != '.' {
         return None;
     }
+    buf.costs.insert(mem.start, 0);
+    buf.queue.push_back(mem.start);
 
-    backtrack(buf);
-    Some(p1)
+    while let Some(cur) = buf.queue.pop_front() {
+        let cost = buf.costs[&cur];
+        if cur == mem.goal {
+            backtrack(mem, buf);
+            return Some(cost);
+        }
+        for step in ORTHOGONAL {
+            let next = cur + step;
+            if map.get(next) != Some('.') || buf.costs.contains_key(&next) {
+                continue;
+            }
+            buf.edges.insert(next, cur);
+            buf.costs.insert(next, cost + 1);
+            buf.queue.push_back(next);
+        }
+    }
+
+    None
 }
 
 // updates buf.path to the shortest path
-fn backtrack(buf: &mut Buf) {
-    let mut cur = GOAL;
+fn backtrack(mem: &Memory, buf: &mut Buf) {
+    let mut cur = mem.goal;
     buf.path.clear();
+    buf.path.insert(cur);
     while let Some(prev) = buf.edges.get(&cur) {
         buf.path.insert(*prev);
-        if *prev == START {
-            return;
-        }
         cur = *prev;
     }
 }
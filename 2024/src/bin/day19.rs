@@ -13,58 +13,169 @@
 //!
 //! Optimization #2: don't need no queue to recurse, we can simply walk the design
 //! from start to end and build the `tally` array as we go.
+//!
+//! Optimization #3: put the patterns in a trie, so every position only walks as
+//! far down the design as some pattern reaches instead of hashing a slice for
+//! every length. The tally now runs from the end of the design to the start, so
+//! it also says whether the rest of the design can be finished from any given
+//! position: that's all it takes to list arrangements without ever backtracking.
+//!
+//! `--arrangements K` lists the first `K` arrangements of every possible design,
+//! in lexicographic order, so `--arrangements 1` gives the smallest one.
 
-use aoc_prelude::HashSet;
-
-const MAX_SIZE: usize = 64;
+use aoc_2024::flag;
+use aoc_prelude::Itertools;
 
-type PatternSet<'a> = HashSet<&'a [u8]>;
+const NONE: u32 = u32::MAX;
 
-struct Counter<'a> {
-    patterns: PatternSet<'a>,
-    max_len: usize,
-    tally: [u64; MAX_SIZE],
+struct Matcher<'a> {
+    patterns: Vec<&'a [u8]>,
+    /// Alphabet slot of every byte, `u8::MAX` for bytes no pattern uses.
+    slots: [u8; 256],
+    width: usize,
+    /// Trie nodes, `width` children each, with the root at 0.
+    children: Vec<u32>,
+    /// Whether a pattern ends at each node.
+    terminal: Vec<bool>,
+    /// Number of ways to finish the design from each position.
+    tally: Vec<u128>,
 }
 
-impl<'a> Counter<'a> {
-    fn new(patterns: PatternSet<'a>) -> Self {
-        let max_len = patterns.iter().map(|p| p.len()).max().expect("no patterns");
-        Self { patterns, max_len, tally: [0; MAX_SIZE] }
+impl<'a> Matcher<'a> {
+    fn new(patterns: Vec<&'a [u8]>) -> Self {
+        let mut slots = [u8::MAX; 256];
+        let mut width = 0;
+        for &b in patterns.iter().flat_map(|p| p.iter()) {
+            if slots[b as usize] == u8::MAX {
+                slots[b as usize] = width as u8;
+                width += 1;
+            }
+        }
+
+        let mut matcher = Self {
+            patterns: Vec::new(),
+            slots,
+            width,
+            children: vec![NONE; width],
+            terminal: vec![false],
+            tally: Vec::new(),
+        };
+        for pattern in &patterns {
+            matcher.insert(pattern);
+        }
+        matcher.patterns = patterns;
+        matcher
     }
 
-    fn count_ways(&mut self, design: &[u8]) -> u64 {
-        let size = design.len();
-        let tally = &mut self.tally;
-        tally.fill(0);
-        tally[0] = 1;
-
-        for start in 0..size {
-            if tally[start] > 0 {
-                for pat_len in 1..=self.max_len.min(size - start) {
-                    let end = start + pat_len;
-                    if self.patterns.contains(&design[start..end]) {
-                        tally[end] += tally[start];
-                    }
-                }
+    fn insert(&mut self, pattern: &[u8]) {
+        let mut node = 0;
+        for &b in pattern {
+            let idx = node * self.width + self.slots[b as usize] as usize;
+            if self.children[idx] == NONE {
+                self.children[idx] = self.terminal.len() as u32;
+                self.children.extend(std::iter::repeat_n(NONE, self.width));
+                self.terminal.push(false);
             }
+            node = self.children[idx] as usize;
+        }
+        self.terminal[node] = true;
+    }
+
+    /// Lengths of the patterns matching `design` from `start` on, shortest first.
+    fn matches<'d>(&'d self, design: &'d [u8], start: usize) -> impl Iterator<Item = usize> + 'd {
+        design[start..]
+            .iter()
+            .scan(0, |node, &b| {
+                let slot = self.slots[b as usize];
+                let next = if slot == u8::MAX {
+                    NONE
+                } else {
+                    self.children[*node * self.width + slot as usize]
+                };
+                (next != NONE).then(|| {
+                    *node = next as usize;
+                    *node
+                })
+            })
+            .positions(|node| self.terminal[node])
+            .map(|len| len + 1)
+    }
+
+    fn count_ways(&mut self, design: &[u8]) -> u128 {
+        let size = design.len();
+        let mut tally = std::mem::take(&mut self.tally);
+        tally.clear();
+        tally.resize(size + 1, 0);
+        tally[size] = 1;
+
+        for start in (0..size).rev() {
+            tally[start] = self.matches(design, start).fold(0, |acc: u128, len| {
+                acc.checked_add(tally[start + len]).expect("too many arrangements for a u128")
+            });
         }
 
-        tally[size]
+        self.tally = tally;
+        self.tally[0]
+    }
+
+    /// The first `limit` arrangements of `design`, in lexicographic order.
+    fn arrangements<'d>(&mut self, design: &'d [u8], limit: usize) -> Vec<Vec<&'d [u8]>> {
+        self.count_ways(design);
+        let mut found = Vec::new();
+        self.collect(design, 0, &mut Vec::new(), &mut found, limit);
+        found
+    }
+
+    fn collect<'d>(
+        &self,
+        design: &'d [u8],
+        start: usize,
+        current: &mut Vec<&'d [u8]>,
+        found: &mut Vec<Vec<&'d [u8]>>,
+        limit: usize,
+    ) {
+        if start == design.len() {
+            found.push(current.clone());
+            return;
+        }
+        // a shorter towel is a prefix of a longer one, so it sorts first
+        for len in self.matches(design, start) {
+            if found.len() == limit {
+                return;
+            }
+            if self.tally[start + len] > 0 {
+                current.push(&design[start..start + len]);
+                self.collect(design, start + len, current, found, limit);
+                current.pop();
+            }
+        }
     }
 }
 
-fn solve() -> (u64, u64) {
+fn solve() -> (usize, u128) {
     let (patterns, designs) = include_str!("../../inputs/19.in").split_once("\n\n").unwrap();
 
-    let mut counter = Counter::new(patterns.split(", ").map(str::as_bytes).collect());
+    let mut matcher = Matcher::new(patterns.split(", ").map(str::as_bytes).collect());
+    assert!(!matcher.patterns.is_empty(), "no patterns");
+
+    let limit =
+        flag("arrangements").map(|k| k.parse::<usize>().expect("--arrangements needs a count"));
 
     designs
         .lines()
         .map(|towel| {
-            let ans = counter.count_ways(towel.as_bytes());
-            ((ans > 0) as u64, ans)
+            let ans = matcher.count_ways(towel.as_bytes());
+            if let Some(limit) = limit.filter(|_| ans > 0) {
+                for arrangement in matcher.arrangements(towel.as_bytes(), limit) {
+                    let towels = arrangement.iter().map(|t| String::from_utf8_lossy(t)).join(", ");
+                    eprintln!("{towel}: {towels}");
+                }
+            }
+            ((ans > 0) as usize, ans)
+        })
+        .fold((0, 0), |acc, cur| {
+            (acc.0 + cur.0, acc.1.checked_add(cur.1).expect("too many arrangements for a u128"))
         })
-        .fold((0, 0), |acc, cur| (acc.0 + cur.0, acc.1 + cur.1))
 }
 
 aoc_2024::main! {
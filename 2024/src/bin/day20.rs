@@ -1,30 +1,31 @@
 //! # Race Condition
 //!
-//! Brute force: we compute a "blast radius" for each tile on the path and
+//! Brute force: we compute a "blast radius" for each tile on the track and
 //! check if a tile within this radius is a valid cheat.
 //!
-//! If the costs associated with taking the cheat (Manhattan distance) plus
-//! the cost of the shunted tile is less than the cost of the current tile =>
-//! it's a valid cheat.
+//! BFS twice to get the distance of every tile from the start and to the end,
+//! so the track can branch: a cheat from `a` to `b` makes for a race of
+//! `from_start[a] + cheat length + to_end[b]` picoseconds, and whatever that
+//! shaves off the fair race is the saving.
+//!
+//! Cheats last up to `--max-cheat 20` picoseconds (2 for part 1) and only count
+//! if they save at least `--min-saving 100`. `--histogram` prints how many cheats
+//! save how much for both parts, like the tables in the puzzle text.
 
 use std::collections::VecDeque;
 
+use aoc_2024::{flag, param};
 use aoc_2dmap::prelude::*;
 use rayon::prelude::*;
 
-const MAP_SIZE: usize = 141;
-const MAX_CHEAT: i32 = 20;
-const MIN_SAVING: i32 = 100;
+const UNREACHABLE: u32 = u32::MAX;
 
-struct Buf {
-    path: Vec<Pos>,
-    costs: Vec<i32>,
-}
-
-impl Default for Buf {
-    fn default() -> Self {
-        Self { path: Vec::with_capacity(10000), costs: vec![-1; MAP_SIZE * MAP_SIZE] }
-    }
+/// Distances along the track, indexed like the map.
+struct Track {
+    from_start: Vec<u32>,
+    to_end: Vec<u32>,
+    /// Fair race time.
+    best: u32,
 }
 
 fn solve() -> (usize, usize) {
@@ -41,76 +42,109 @@ fn solve() -> (usize, usize) {
     map[start] = '.';
     map[goal] = '.';
 
-    let buf = dfs(&map, start, goal).expect("no path!?");
-    buf.path
-        .into_par_iter()
-        .map(|pos| find_cheats(pos, &buf.costs))
-        .reduce(|| (0, 0), |acc, val| (acc.0 + val.0, acc.1 + val.1))
-}
-
-fn dfs(map: &Map<char>, start: Pos, goal: Pos) -> Option<Buf> {
-    let mut buf = Buf::default();
-    let mut queue = VecDeque::with_capacity(1024);
-    queue.push_back((0, start));
+    let from_start = bfs(&map, start);
+    let best = from_start[index(&map, goal)];
+    assert_ne!(best, UNREACHABLE, "no path!?");
+    let track = Track { from_start, to_end: bfs(&map, goal), best };
+
+    let min_saving = param("min-saving", 100).max(1);
+    let parts = [2, param("max-cheat", 20)].map(|max_cheat| {
+        map.iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .fold(
+                || vec![0; best as usize + 1],
+                |mut histogram, pos| {
+                    track.cheats(&map, pos, max_cheat, min_saving, &mut histogram);
+                    histogram
+                },
+            )
+            .reduce(
+                || vec![0; best as usize + 1],
+                |mut acc, histogram| {
+                    acc.iter_mut().zip(histogram).for_each(|(a, h)| *a += h);
+                    acc
+                },
+            )
+    });
+
+    if flag("histogram").is_some() {
+        for (part, histogram) in parts.iter().enumerate() {
+            eprintln!("Part {}:", part + 1);
+            for (saving, &count) in histogram.iter().enumerate().filter(|(_, &c)| c > 0) {
+                match count {
+                    1 => eprintln!("There is one cheat that saves {saving} picoseconds."),
+                    _ => eprintln!("There are {count} cheats that save {saving} picoseconds."),
+                }
+            }
+        }
+    }
 
-    while let Some((cost, cur)) = queue.pop_back() {
-        buf.path.push(cur);
-        buf.costs[index(&cur)] = cost;
+    let [p1, p2] = parts.map(|histogram| histogram.iter().sum());
+    (p1, p2)
+}
 
-        if cur == goal {
-            return Some(buf);
+impl Track {
+    /// Tallies the cheats starting at `pos` by how many picoseconds they save.
+    fn cheats(
+        &self,
+        map: &Map<char>,
+        pos: Pos,
+        max_cheat: i32,
+        min_saving: u32,
+        histogram: &mut [usize],
+    ) {
+        let before = self.from_start[index(map, pos)];
+        if before == UNREACHABLE {
+            return;
         }
 
-        for step in ORTHOGONAL {
-            let next = cur + step;
+        // the Manhattan rhomboid of radius `max_cheat` around `pos`
+        for dy in -max_cheat..=max_cheat {
+            let reach = max_cheat - dy.abs();
+            for dx in -reach..=reach {
+                let dist = (dx.abs() + dy.abs()) as u32;
+                // a cheat of 1 just walks onto the next tile of the track
+                if dist < 2 {
+                    continue;
+                }
 
-            if map.get(next) == Some('.') {
-                let idx = index(&next);
+                let end = pos + Pos::new(dx, dy);
+                if !map.within(end) {
+                    continue;
+                }
+                let after = self.to_end[index(map, end)];
+                if after == UNREACHABLE {
+                    continue;
+                }
 
-                if buf.costs[idx] == -1 {
-                    queue.push_back((cost + 1, next));
+                let race = before + dist + after;
+                if race + min_saving <= self.best {
+                    histogram[(self.best - race) as usize] += 1;
                 }
             }
         }
     }
-    None
 }
 
-fn find_cheats(pos: Pos, costs: &[i32]) -> (usize, usize) {
-    let mut p1 = 0;
-    let mut p2 = 0;
-
-    let cur_idx = index(&pos);
-
-    // generate the Manhattan rhomboid of radius MAX_CHEAT around `pos`
-    for x_off in 1..=MAX_CHEAT {
-        for y_off in 0..=(MAX_CHEAT - x_off) {
-            let dist = x_off + y_off;
-
-            // prune the first layer - cheat size of 1 is not really a cheat,
-            // the DFS will go there anyway
-            if dist < 2 {
-                continue;
-            }
-
-            for offset in rotations(x_off, y_off) {
-                let new_pos = pos + offset;
-
-                if in_bounds(&new_pos) {
-                    let new_idx = index(&new_pos);
+/// Distance of every tile from `from`, `UNREACHABLE` for walls and cut off tiles.
+fn bfs(map: &Map<char>, from: Pos) -> Vec<u32> {
+    let mut costs = vec![UNREACHABLE; (map.size.x * map.size.y) as usize];
+    let mut queue = VecDeque::with_capacity(1024);
+    costs[index(map, from)] = 0;
+    queue.push_back(from);
 
-                    if costs[new_idx] != -1 && costs[new_idx] + dist + MIN_SAVING <= costs[cur_idx]
-                    {
-                        if dist == 2 {
-                            p1 += 1;
-                        }
-                        p2 += 1;
-                    }
-                }
+    while let Some(cur) = queue.pop_front() {
+        let cost = costs[index(map, cur)];
+        for step in ORTHOGONAL {
+            let next = cur + step;
+            if map.get(next) == Some('.') && costs[index(map, next)] == UNREACHABLE {
+                costs[index(map, next)] = cost + 1;
+                queue.push_back(next);
             }
         }
     }
-    (p1, p2)
+    costs
 }
 
 #[inline(always)]
@@ -119,17 +153,7 @@ fn find_tile(map: &Map<char>, tile: char) -> Pos {
 }
 
 #[inline(always)]
-fn index(pos: &Pos) -> usize { (pos.y as usize) * MAP_SIZE + (pos.x as usize) }
-
-#[inline(always)]
-fn rotations(x: i32, y: i32) -> [Pos; 4] {
-    [Pos::new(x, y), Pos::new(-y, x), Pos::new(-x, -y), Pos::new(y, -x)]
-}
-
-#[inline(always)]
-fn in_bounds(pos: &Pos) -> bool {
-    pos.x > 0 && pos.y > 0 && pos.x < (MAP_SIZE as i32) && pos.y < (MAP_SIZE as i32)
-}
+fn index(map: &Map<char>, pos: Pos) -> usize { (pos.y * map.size.x + pos.x) as usize }
 
 aoc_2024::main! {
     solve()
@@ -7,231 +7,344 @@
 //! depth for a given `&[char]` sequence by checking the transition map directly
 //! (if the depth is 1) or by taking all possible transition sequences and passing
 //! them one level down, then taking the minimum.
-
-use std::{iter::once, mem};
-
-use aoc_2dmap::prelude::{Map, Pos};
+//!
+//! Optimization #2: every robot starts and ends its moves on `A`, so the cost of
+//! a sequence is the sum of the costs of its key-to-key moves. That turns the
+//! recursion into a table per robot layer with one entry per pair of keys, each
+//! layer built from the one below it. No sequences to hash, so nothing to collide.
+//!
+//! Keypads are plain layouts with `.` for the gap and rows separated by commas,
+//! so they can be swapped out with `--numeric-pad 789,456,123,.0A` and
+//! `--arrow-pad .^A,<v>`. The number of robots on arrow pads is `--robots 2` for
+//! part 1 and `--deep-robots 25` for part 2.
+//!
+//! The tables also say which way is cheapest for every move, so an optimal
+//! sequence can be rebuilt one layer at a time. `--presses` prints what every
+//! robot presses for each code in `--part 1|2` (part 2 by default). Layers longer
+//! than `--max-presses 200` are summed up instead, as how many times each run of
+//! presses up to an `A` shows up. `--check <presses>` runs the presses through the
+//! robots of the same part and reports what they type and any time one of them
+//! points at the gap.
+
+use std::{cmp::Reverse, iter::once};
+
+use aoc_2024::{extract_nums, flag, param};
+use aoc_2dmap::prelude::*;
 use aoc_prelude::{HashMap, Itertools};
-use aoc_2024::extract_nums;
 
-type TrMap = Vec<Vec<Transition>>;
-type Int = u64;
+type Int = u128;
 
-const MAX_KEYS: u8 = 11;
+const NUMERIC_PAD: &str = "789,456,123,.0A";
+const ARROW_PAD: &str = ".^A,<v>";
+const DIR_CHARS: [char; 4] = ['^', '>', 'v', '<'];
 
-const UP: u8 = 1;
-const LEFT: u8 = 2;
-const DOWN: u8 = 3;
-const RIGHT: u8 = 4;
-const ENTER: u8 = 5;
-
-#[derive(Copy, Clone, Debug)]
-struct Transition {
-    bases: [Pos; 2],
-    num_moves: u8,
-    sequence: u8,
+struct Keypad {
+    map: Map<char>,
+    keys: Vec<Pos>,
+    /// Every shortest way between two keys, as arrow presses followed by `A`.
+    /// Indexed by `from * keys + to`.
+    paths: Vec<Vec<Vec<char>>>,
 }
 
-impl Transition {
-    fn is_valid(&self, map: &Map<char>, from_pos: Pos) -> bool {
-        let mut cur = from_pos;
-        for off in self.offsets() {
-            cur += off;
-            if map.get(cur) == Some('.') {
-                return false;
-            }
-        }
-        true
+impl Keypad {
+    fn parse(layout: &str) -> Self {
+        let rows = layout.split([',', '\n']).filter(|row| !row.is_empty()).collect_vec();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        assert!(
+            width > 0 && rows.iter().all(|row| row.chars().count() == width),
+            "keypad rows must be equally wide: {layout}"
+        );
+
+        let map = Map::new((width, rows.len()), rows.iter().flat_map(|row| row.chars()));
+        let keys = map.iter().filter(|pos| map[pos] != '.').collect_vec();
+        assert!(keys.iter().map(|pos| map[pos]).all_unique(), "duplicate keys: {layout}");
+
+        let mut pad = Self { map, keys, paths: Vec::new() };
+        pad.paths = (0..pad.len())
+            .cartesian_product(0..pad.len())
+            .map(|(from, to)| pad.shortest_paths(from, to))
+            .collect();
+        pad
     }
 
-    fn as_bytes(&self) -> impl Iterator<Item = u8> + '_ {
-        self.offsets()
-            .map(|o| match (o.x, o.y) {
-                (0, -1) => UP,
-                (-1, 0) => LEFT,
-                (0, 1) => DOWN,
-                (1, 0) => RIGHT,
-                _ => panic!(),
-            })
-            .chain(once(ENTER))
-    }
+    fn len(&self) -> usize { self.keys.len() }
 
-    fn offsets(&self) -> impl Iterator<Item = Pos> + '_ {
-        (0..self.num_moves).map(|n| {
-            // 0 selects the 1nd base (Y-axis)
-            // 1 selects the 2nd base (X-axis)
-            let base_idx = ((self.sequence >> n) & 1) as usize;
-            self.bases[base_idx]
-        })
+    fn index(&self, key: char) -> Option<usize> {
+        self.keys.iter().position(|pos| self.map[pos] == key)
     }
-}
 
-fn solve() -> (Int, Int) {
-    let num_transitions = make_tr_map(&Map::new((3, 4), "789456123.0A".chars()), num_repr);
-    let arrow_transitions = make_tr_map(&Map::new((3, 2), ".^A<v>".chars()), arrow_repr);
+    fn paths(&self, from: usize, to: usize) -> &[Vec<char>] { &self.paths[from * self.len() + to] }
 
-    let mut cache = HashMap::new();
-    let (p1, p2) = include_str!("../../inputs/21.in")
-        .lines()
-        .map(|line| {
-            let num = extract_nums::<u64>(line).next().unwrap();
-            let goal = line.chars().collect_vec();
-            let sequences = possible_sequences(&goal, &num_transitions, num_repr);
+    fn is_key(&self, pos: Pos) -> bool { self.map.get(pos).is_some_and(|c| c != '.') }
 
-            let min_len_2 = sequences
-                .iter()
-                .map(|s| sequence_length(s, 2, &arrow_transitions, &mut cache))
-                .min()
-                .unwrap();
-
-            let min_len_25 = sequences
-                .iter()
-                .map(|s| sequence_length(s, 25, &arrow_transitions, &mut cache))
-                .min()
-                .unwrap();
+    fn shortest_paths(&self, from: usize, to: usize) -> Vec<Vec<char>> {
+        let (start, goal) = (self.keys[from], self.keys[to]);
 
-            (num * min_len_2, num * min_len_25)
-        })
-        .fold((0, 0), |acc: (Int, Int), cur| {
-            (acc.0.checked_add(cur.0).unwrap(), acc.1.checked_add(cur.1).unwrap())
-        });
-
-    (p1, p2)
-}
-
-fn make_tr_map<F: Fn(char) -> u8>(pad: &Map<char>, repr_fn: F) -> TrMap {
-    let mut transitions = vec![Vec::new(); (MAX_KEYS * MAX_KEYS) as usize];
-
-    for from_pos in pad.iter() {
-        let from_ch = pad[from_pos];
-
-        if from_ch == '.' {
-            continue;
+        // distance of every key to the goal, so we only ever step closer
+        let mut dist: HashMap<Pos, i32> = HashMap::default();
+        dist.insert(goal, 0);
+        let mut frontier = vec![goal];
+        while !frontier.is_empty() {
+            let mut next_frontier = Vec::new();
+            for pos in frontier {
+                for next in ORTHOGONAL.map(|step| pos + step) {
+                    if self.is_key(next) && !dist.contains_key(&next) {
+                        dist.insert(next, dist[&pos] + 1);
+                        next_frontier.push(next);
+                    }
+                }
+            }
+            frontier = next_frontier;
         }
-
-        for to_pos in pad.iter() {
-            let to_ch = pad[to_pos];
-
-            if to_ch == '.' {
+        assert!(
+            dist.contains_key(&start),
+            "no way from {} to {} on the keypad",
+            self.map[start],
+            self.map[goal]
+        );
+
+        let mut paths = Vec::new();
+        let mut stack = vec![(start, Vec::new())];
+        while let Some((pos, mut path)) = stack.pop() {
+            if pos == goal {
+                path.push('A');
+                paths.push(path);
                 continue;
             }
-
-            let bases = (to_pos - from_pos).signum();
-            let num_x = (to_pos.x - from_pos.x).unsigned_abs();
-            let num_moves = (num_x + (to_pos.y - from_pos.y).unsigned_abs()) as u8;
-
-            for sequence in 0..=((1u8 << num_moves) - 1) {
-                if sequence.count_ones() == num_x {
-                    // We have a number whose 1 bits indicate a move on the X axis
-                    // and whose 0 bits indicate a move on the Y axis
-                    let transition = Transition {
-                        bases: [Pos::new(0, bases.y), Pos::new(bases.x, 0)],
-                        num_moves,
-                        sequence,
-                    };
-
-                    if transition.is_valid(pad, from_pos) {
-                        transitions[tr_key(repr_fn(from_ch), repr_fn(to_ch))].push(transition)
-                    }
+            for (dir, step) in ORTHOGONAL.iter().enumerate() {
+                let next = pos + *step;
+                if dist.get(&next) == Some(&(dist[&pos] - 1)) {
+                    let mut path = path.clone();
+                    path.push(DIR_CHARS[dir]);
+                    stack.push((next, path));
                 }
             }
         }
+        paths
     }
-    transitions
-}
-
-fn possible_sequences<F: Fn(char) -> u8>(
-    goal: &[char],
-    tr_map: &TrMap,
-    repr_fn: F,
-) -> Vec<Vec<u8>> {
-    let (mut paths, mut new_paths) = (vec![Vec::new()], vec![Vec::new()]);
-
-    let (mut goal_i, mut cur_ch) = (0, 'A');
-
-    while goal_i < goal.len() {
-        let key = tr_key(repr_fn(cur_ch), repr_fn(goal[goal_i]));
-        let transitions = &tr_map[key];
 
-        new_paths.clear();
+    fn index_all(&self, keys: impl IntoIterator<Item = char>) -> Vec<usize> {
+        keys.into_iter()
+            .map(|key| self.index(key).unwrap_or_else(|| panic!("no {key:?} key on the keypad")))
+            .collect()
+    }
+}
 
-        for transition in transitions {
-            for path in &paths {
-                let mut new_path = path.clone();
-                new_path.extend(transition.as_bytes());
-                new_paths.push(new_path);
+fn solve() -> (Int, Int) {
+    let door = Keypad::parse(&flag("numeric-pad").unwrap_or(NUMERIC_PAD.into()));
+    let arrows = Keypad::parse(&flag("arrow-pad").unwrap_or(ARROW_PAD.into()));
+    assert!(door.index('A').is_some(), "the numeric pad needs an A key");
+    assert!(
+        DIR_CHARS.into_iter().chain(once('A')).all(|key| arrows.index(key).is_some()),
+        "the arrow pad needs ^, >, v, < and A keys"
+    );
+
+    let robots = [param("robots", 2), param("deep-robots", 25)];
+    let layers = arrow_layers(&arrows, robots.into_iter().max().unwrap());
+    let door_costs = robots.map(|robots| move_costs(&door, &arrows, &layers[robots]));
+
+    let codes = include_str!("../../inputs/21.in").lines().filter(|line| !line.is_empty());
+    let debug = robots[param::<usize>("part", 2) - 1];
+
+    if flag("presses").is_some() {
+        let max_presses = param("max-presses", 200);
+        for code in codes.clone() {
+            eprintln!("{code}:");
+            for (robot, layer) in presses(&door, &arrows, &layers[..=debug], code, max_presses) {
+                eprintln!("  {:>10}: {layer}", who(robot, debug));
             }
         }
+    }
+    if let Some(presses) = flag("check") {
+        let run = simulate(&door, &arrows, debug, &presses).unwrap_or_else(|err| panic!("{err}"));
+        eprintln!("Typed {:?}", run.typed);
+        for (press, robot) in run.gap_visits {
+            eprintln!("Press {press}: {} points at the gap", who(robot, debug));
+        }
+    }
 
-        mem::swap(&mut paths, &mut new_paths);
+    codes
+        .map(|line| {
+            let num = extract_nums::<Int>(line).next().unwrap_or(0);
+            let code = door.index_all(line.chars());
+            door_costs.each_ref().map(|costs| {
+                typing_cost(&door, &code, costs)
+                    .checked_mul(num)
+                    .expect("too many presses for a u128")
+            })
+        })
+        .fold((0, 0), |acc, [p1, p2]| {
+            (acc.0.checked_add(p1).unwrap(), acc.1.checked_add(p2).unwrap())
+        })
+}
 
-        cur_ch = goal[goal_i];
-        goal_i += 1;
+/// Cost tables of the arrow pads: `layers[n]` is what it takes to get a pad with
+/// `n` robots in between to move from one key to another and press it.
+fn arrow_layers(arrows: &Keypad, robots: usize) -> Vec<Vec<Int>> {
+    let mut layers = vec![vec![1; arrows.len() * arrows.len()]];
+    for n in 0..robots {
+        layers.push(move_costs(arrows, arrows, &layers[n]));
     }
-    paths
+    layers
 }
 
-fn sequence_length(
-    seq: &[u8],
-    depth: u64,
-    tr_map: &TrMap,
-    cache: &mut HashMap<(u64, u64), Int>,
-) -> Int {
-    // We've got maximum 14 arrow + 'A' key presses after the first stage
-    // and each key value is represented on 4 bits.
-    let mut key: u64 = 0;
-    for ch in seq {
-        key = (key << 4) ^ (*ch as u64)
-    }
+/// Cheapest way to move between any two keys of `pad` and press the second one,
+/// when its robot is driven from `arrows` at `costs`.
+fn move_costs(pad: &Keypad, arrows: &Keypad, costs: &[Int]) -> Vec<Int> {
+    (0..pad.len())
+        .cartesian_product(0..pad.len())
+        .map(|(from, to)| best_path(pad, arrows, from, to, costs).0)
+        .collect()
+}
+
+/// Cheapest way to move between two keys of `pad` and press the second one, as
+/// the keys to press on `arrows`, along with what that costs.
+fn best_path(
+    pad: &Keypad,
+    arrows: &Keypad,
+    from: usize,
+    to: usize,
+    costs: &[Int],
+) -> (Int, Vec<usize>) {
+    pad.paths(from, to)
+        .iter()
+        .map(|path| {
+            let keys = arrows.index_all(path.iter().copied());
+            (typing_cost(arrows, &keys, costs), keys)
+        })
+        .min()
+        .unwrap()
+}
+
+/// Presses it takes to type `keys` on `pad`, starting from `A`.
+fn typing_cost(pad: &Keypad, keys: &[usize], costs: &[Int]) -> Int {
+    let start = pad.index('A').unwrap();
+    once(&start)
+        .chain(keys)
+        .tuple_windows()
+        .map(|(&from, &to)| costs[from * pad.len() + to])
+        .fold(0, |acc: Int, cost| acc.checked_add(cost).expect("too many presses for a u128"))
+}
 
-    if cache.contains_key(&(key, depth)) {
-        return cache[&(key, depth)];
+/// What every layer presses to type `code`, from the door robot down to you.
+/// `layers` holds one cost table per robot on an arrow pad and one for you.
+fn presses(
+    door: &Keypad,
+    arrows: &Keypad,
+    layers: &[Vec<Int>],
+    code: &str,
+    max_presses: usize,
+) -> Vec<(usize, String)> {
+    let code = door.index_all(code.chars());
+    let start = door.index('A').unwrap();
+    let mut moves: HashMap<(usize, usize), Int> = HashMap::default();
+    for (&from, &to) in once(&start).chain(&code).tuple_windows() {
+        *moves.entry((from, to)).or_insert(0) += 1;
     }
 
-    // Robots start on the 'A' (ENTER) key
-    let ret = once(ENTER)
-        .chain(seq.iter().copied())
-        .zip(seq)
-        .map(|(from_b, &to_b)| {
-            let tx = &tr_map[tr_key(from_b, to_b)];
-
-            if depth == 1 {
-                (tx[0].num_moves as Int) + 1 // base Transitions don't include the final 'A'
-            } else {
-                tx.iter()
-                    .map(|t| sequence_length(&t.as_bytes().collect_vec(), depth - 1, tr_map, cache))
-                    .min()
-                    .unwrap()
+    let arrow_start = arrows.index('A').unwrap();
+    let mut literal = Some(code);
+    let mut spelled = vec![(0, spell(door, literal.as_deref().unwrap()))];
+    for (robot, costs) in layers.iter().rev().enumerate() {
+        let pad = if robot == 0 { door } else { arrows };
+        let start = pad.index('A').unwrap();
+
+        // every move on the layer above becomes a run of presses ending in `A`
+        let mut runs: HashMap<Vec<usize>, Int> = HashMap::default();
+        let mut next_moves: HashMap<(usize, usize), Int> = HashMap::default();
+        for (&(from, to), &count) in &moves {
+            let (_, keys) = best_path(pad, arrows, from, to, costs);
+            for (&from, &to) in once(&arrow_start).chain(&keys).tuple_windows() {
+                *next_moves.entry((from, to)).or_insert(0) += count;
             }
-        })
-        .sum();
+            *runs.entry(keys).or_insert(0) += count;
+        }
+        moves = next_moves;
+
+        let len = runs
+            .iter()
+            .map(|(keys, &count)| keys.len() as Int * count)
+            .fold(0, |acc: Int, len| acc.checked_add(len).expect("too many presses for a u128"));
+        literal = literal.filter(|_| len <= max_presses as Int).map(|keys| {
+            once(&start)
+                .chain(&keys)
+                .tuple_windows()
+                .flat_map(|(&from, &to)| best_path(pad, arrows, from, to, costs).1)
+                .collect()
+        });
 
-    cache.insert((key, depth), ret);
-    ret
-}
+        let layer = match &literal {
+            Some(keys) => spell(arrows, keys),
+            None => runs
+                .iter()
+                .sorted_by_key(|(keys, &count)| (Reverse(count), spell(arrows, keys)))
+                .map(|(keys, count)| format!("{count}x{}", spell(arrows, keys)))
+                .join(" "),
+        };
+        spelled.push((robot + 1, format!("{layer} ({len} presses)")));
+    }
 
-#[inline]
-fn num_repr(c: char) -> u8 {
-    if c == 'A' {
-        return 10;
+    if let Some(keys) = literal {
+        let run = simulate(door, arrows, layers.len() - 1, &spell(arrows, &keys)).unwrap();
+        assert!(run.gap_visits.is_empty(), "rebuilt presses point at the gap");
+        assert_eq!(run.typed, spelled[0].1, "rebuilt presses type the wrong code");
     }
-    (c as u8 - b'0') as _
+    spelled
 }
 
-#[inline]
-fn arrow_repr(c: char) -> u8 {
-    match c {
-        '^' => UP,
-        '<' => LEFT,
-        'v' => DOWN,
-        '>' => RIGHT,
-        'A' => ENTER,
-        _ => panic!(),
+/// Robot 0 is at the door, the last one is you.
+fn who(robot: usize, robots: usize) -> String {
+    match robot {
+        0 => "door robot".into(),
+        _ if robot > robots => "you".into(),
+        _ => format!("robot {robot}"),
     }
 }
 
-#[inline]
-fn tr_key(from_b: u8, to_b: u8) -> usize { (from_b * MAX_KEYS + to_b) as _ }
+fn spell(pad: &Keypad, keys: &[usize]) -> String {
+    keys.iter().map(|&key| pad.map[pad.keys[key]]).collect()
+}
+
+/// What the robots type, along with every press that made one of them point at
+/// the gap, as the press number and the robot.
+struct Run {
+    typed: String,
+    gap_visits: Vec<(usize, usize)>,
+}
+
+/// Feeds `presses` through `robots` robots on arrow pads and the door robot. A
+/// robot that would point at the gap or off its keypad stays where it is.
+fn simulate(door: &Keypad, arrows: &Keypad, robots: usize, presses: &str) -> Result<Run, String> {
+    let mut arms = (0..=robots)
+        .map(|robot| if robot == 0 { door } else { arrows })
+        .map(|pad| pad.keys[pad.index('A').unwrap()])
+        .collect_vec();
+    let mut run = Run { typed: String::new(), gap_visits: Vec::new() };
+
+    for (idx, press) in presses.chars().filter(|c| !c.is_whitespace()).enumerate() {
+        let mut key = press;
+        for robot in (0..=robots).rev() {
+            let pad = if robot == 0 { door } else { arrows };
+            if let Some(dir) = DIR_CHARS.iter().position(|&c| c == key) {
+                let next = arms[robot] + ORTHOGONAL[dir];
+                if pad.is_key(next) {
+                    arms[robot] = next;
+                } else {
+                    run.gap_visits.push((idx + 1, robot));
+                }
+                break;
+            }
+            if key != 'A' {
+                return Err(format!("invalid press {key:?} at {}", idx + 1));
+            }
+            key = pad.map[arms[robot]];
+            if robot == 0 {
+                run.typed.push(key);
+            }
+        }
+    }
+    Ok(run)
+}
 
 aoc_2024::main! {
     solve()
@@ -1,8 +1,13 @@
 //! # Monkey Market
 //!
 //! Fancy brute-force with rayon parallelism + updating the chunk tally as
-//! we go. Hashmaps replaced with flat arrays, indexed by four -9 <-> +9
-//! integers.
+//! we go. Hashmaps replaced with flat arrays, indexed by the last few -9 <-> +9
+//! changes read as a base 19 number.
+//!
+//! Everything about the market can be tweaked: `--steps 2000` secrets per buyer,
+//! a `--window 4` changes long sequence for the monkey to watch and the mixing
+//! steps with `--mix "*64,/32,*2048" --prune 16777216`. `--best` prints the
+//! winning sequence of changes and what every buyer sells for with it.
 
 use std::{
     sync::{
@@ -12,27 +17,138 @@ use std::{
     thread::available_parallelism,
 };
 
-use aoc_2024::extract_nums;
+use aoc_2024::{extract_nums, flag, param};
 use aoc_prelude::Itertools;
 use rayon::prelude::*;
 
-const MOD: Int = (1 << 24) - 1;
-const NUM_KEYS: usize = 19usize.pow(4);
-
 type Int = u64;
-type Key = [i8; 4];
-type Map = [u16; NUM_KEYS];
 
-fn solve() -> (Int, u16) {
+/// One mixing step: work out a value from the secret, then xor it in and prune.
+#[derive(Copy, Clone, Debug)]
+enum Mix {
+    Mul(Int),
+    Div(Int),
+    /// Multiplication by a power of two, when pruning keeps the low bits anyway.
+    Shl(u32),
+    /// Division by a power of two.
+    Shr(u32),
+}
+
+impl Mix {
+    fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let by = s
+            .get(1..)
+            .and_then(|by| by.parse::<Int>().ok())
+            .filter(|&by| by > 0)
+            .ok_or_else(|| format!("invalid mixing step {s:?}, expected e.g. *64 or /32"))?;
+        match s.as_bytes()[0] {
+            b'*' => Ok(Mix::Mul(by)),
+            b'/' if by.is_power_of_two() => Ok(Mix::Shr(by.trailing_zeros())),
+            b'/' => Ok(Mix::Div(by)),
+            _ => Err(format!("invalid mixing step {s:?}, expected e.g. *64 or /32")),
+        }
+    }
+
+    fn apply(&self, n: Int) -> Int {
+        match *self {
+            Mix::Mul(by) => n.checked_mul(by).expect("secret overflow, try a smaller --prune"),
+            Mix::Div(by) => n / by,
+            Mix::Shl(by) => n << by,
+            Mix::Shr(by) => n >> by,
+        }
+    }
+}
+
+struct Market {
+    mix: Vec<Mix>,
+    prune: Int,
+    /// Pruning as a bit mask, when `prune` is a power of two.
+    mask: Option<Int>,
+    steps: usize,
+    window: u32,
+}
+
+impl Market {
+    fn new(mut mix: Vec<Mix>, prune: Int, steps: usize, window: u32) -> Self {
+        assert!(prune > 0, "--prune must be positive");
+        assert!((1..=5).contains(&window), "--window must be between 1 and 5");
+
+        let mask = prune.is_power_of_two().then(|| prune - 1);
+        if mask.is_some() {
+            for step in &mut mix {
+                if let Mix::Mul(by) = *step {
+                    if by.is_power_of_two() {
+                        *step = Mix::Shl(by.trailing_zeros());
+                    }
+                }
+            }
+        }
+        Self { mix, prune, mask, steps, window }
+    }
+
+    fn next(&self, mut n: Int) -> Int {
+        for mix in &self.mix {
+            n ^= mix.apply(n);
+            n = match self.mask {
+                Some(mask) => n & mask,
+                None => n % self.prune,
+            };
+        }
+        n
+    }
+
+    /// Number of distinct change sequences.
+    fn keys(&self) -> usize { 19usize.pow(self.window) }
+
+    /// Walks a buyer's secrets, calling `visit` with every price that comes after
+    /// a full window of changes, along with the index of those changes. Returns
+    /// the last secret.
+    fn walk(&self, initial: Int, mut visit: impl FnMut(usize, Int)) -> Int {
+        let keys = self.keys();
+        let mut n = initial;
+        let mut price = n % 10;
+        let mut key = 0;
+
+        for step in 0..self.steps {
+            n = self.next(n);
+            let new_price = n % 10;
+            key = (key * 19 + (new_price + 9 - price) as usize) % keys;
+            if step + 1 >= self.window as usize {
+                visit(key, new_price);
+            }
+            price = new_price;
+        }
+        n
+    }
+
+    /// The changes behind a key, oldest first.
+    fn changes(&self, key: usize) -> Vec<i32> {
+        (0..self.window).rev().map(|pos| (key / 19usize.pow(pos) % 19) as i32 - 9).collect()
+    }
+}
+
+fn solve() -> (Int, u32) {
     let input = include_str!("../../inputs/22.in");
     let nums = input.lines().filter_map(|l| extract_nums(l).next()).collect_vec();
 
+    let market = Market::new(
+        flag("mix")
+            .unwrap_or("*64,/32,*2048".into())
+            .split(',')
+            .map(|s| Mix::parse(s).unwrap_or_else(|err| panic!("{err}")))
+            .collect(),
+        param("prune", 1 << 24),
+        param("steps", 2000),
+        param("window", 4),
+    );
+
     let total = AtomicU64::new(0);
-    let tally = Mutex::new([0u16; NUM_KEYS]);
+    let tally = Mutex::new(vec![0u32; market.keys()]);
 
     nums.chunks((nums.len() / available_parallelism().unwrap().get()) + 1)
         .par_bridge()
-        .map(process_chunk)
+        .map(|chunk| process_chunk(&market, chunk))
         .for_each(|(chunk_total, chunk_tally)| {
             let mut tally = tally.lock().unwrap();
             for (idx, el) in tally.iter_mut().enumerate() {
@@ -41,59 +157,43 @@ fn solve() -> (Int, u16) {
             total.fetch_add(chunk_total, Ordering::Relaxed);
         });
 
-    (total.load(Ordering::Relaxed), tally.into_inner().unwrap().into_iter().max().unwrap())
-}
-
-fn hash(n: Int) -> Int {
-    let mut n = n;
+    let tally = tally.into_inner().unwrap();
+    // the first of the best keys, so ties always go the same way
+    let (best, bananas) =
+        tally.iter().enumerate().rev().max_by_key(|(_, &bananas)| bananas).unwrap();
+
+    if flag("best").is_some() {
+        eprintln!("Best changes: {}", market.changes(best).iter().join(","));
+        for initial in &nums {
+            let mut sold = None;
+            market.walk(*initial, |key, price| {
+                if key == best && sold.is_none() {
+                    sold = Some(price);
+                }
+            });
+            eprintln!("{initial}: {}", sold.map_or("-".into(), |price| price.to_string()));
+        }
+    }
 
-    n = (n ^ n << 6) & MOD;
-    n = n ^ n >> 5;
-    (n ^ n << 11) & MOD
+    (total.load(Ordering::Relaxed), *bananas)
 }
 
-fn process_chunk(chunk: &[Int]) -> (Int, Map) {
+fn process_chunk(market: &Market, chunk: &[Int]) -> (Int, Vec<u32>) {
     let mut total = 0;
-    let mut tally = [0u16; NUM_KEYS];
-    let mut seen = [u16::MAX; NUM_KEYS];
-
-    for (buyer_id, initial) in chunk.iter().enumerate() {
-        let buyer_id = buyer_id as u16;
-
-        let mut p = initial % 10;
-        let mut n = *initial;
-
-        let mut key = [0; 4];
-
-        for j in 0..2000 {
-            n = hash(n);
-            let new_p = n % 10;
-            let delta = new_p - p;
-            if j < 4 {
-                key[j] = (delta + 9) as i8;
-            } else {
-                (key[0], key[1], key[2]) = (key[1], key[2], key[3]);
-                key[3] = (delta + 9) as i8;
-                let idx = index(key);
-                if seen[idx] != buyer_id {
-                    seen[idx] = buyer_id;
-                    tally[idx] += new_p as u16;
-                }
+    let mut tally = vec![0u32; market.keys()];
+    let mut seen = vec![u32::MAX; market.keys()];
+
+    for (buyer_id, initial) in (0..).zip(chunk) {
+        total += market.walk(*initial, |key, price| {
+            if seen[key] != buyer_id {
+                seen[key] = buyer_id;
+                tally[key] += price as u32;
             }
-            p = new_p;
-        }
-        total += n;
+        });
     }
     (total, tally)
 }
 
-fn index(key: Key) -> usize {
-    let mut idx = key[3] as usize;
-    idx = 19 * idx + key[2] as usize;
-    idx = 19 * idx + key[1] as usize;
-    19 * idx + key[0] as usize
-}
-
 aoc_2024::main! {
     solve()
 }
@@ -31,6 +31,27 @@ pub fn reverse<K, V: Eq + Hash>(h: HashMap<K, V>) -> HashMap<V, K> {
     h.into_iter().map(|(k, v)| (v, k)).collect()
 }
 
+/// Value passed after `--name` on the command line, or an empty string for
+/// a bare `--name` switch.
+pub fn flag(name: &str) -> Option<String> { find_flag(std::env::args().skip(1), name) }
+
+/// Parses the value passed after `--name`, falling back to `default` if missing.
+pub fn param<T: FromStr>(name: &str, default: T) -> T {
+    flag(name).map_or(default, |val| {
+        val.parse().unwrap_or_else(|_| panic!("invalid value for --{name}: {val}"))
+    })
+}
+
+fn find_flag(args: impl Iterator<Item = String>, name: &str) -> Option<String> {
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if arg.strip_prefix("--") == Some(name) {
+            return Some(args.next_if(|val| !val.starts_with("--")).unwrap_or_default());
+        }
+    }
+    None
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct BitSet<const N: usize> {
     inner: [u128; N],
@@ -65,7 +86,17 @@ impl<const N: usize> BitOr<usize> for BitSet<N> {
 
 #[cfg(test)]
 mod tests {
-    use super::BitSet;
+    use super::{find_flag, BitSet};
+
+    #[test]
+    fn test_find_flag() {
+        let args = || ["--trace", "json", "--verbose", "--size", "7"].map(String::from).into_iter();
+
+        assert_eq!(find_flag(args(), "trace").as_deref(), Some("json"));
+        assert_eq!(find_flag(args(), "verbose").as_deref(), Some(""));
+        assert_eq!(find_flag(args(), "size").as_deref(), Some("7"));
+        assert_eq!(find_flag(args(), "json"), None);
+    }
 
     #[test]
     fn test_bitset() {